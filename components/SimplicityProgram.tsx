@@ -5,7 +5,7 @@ import { highlightSimplicitySyntax } from '@/utils/syntaxHighlighter';
 import { useApp } from '@/contexts/AppContext';
 import { PresetDropdown } from './PresetDropdown';
 import { InfoMenu } from './InfoMenu';
-import { proxyApi } from '@/services/proxyApi';
+import { proxyApi, CompileDiagnosticError } from '@/services/proxyApi';
 import { sendGAEvent } from '@next/third-parties/google';
 
 const PRESETS = [
@@ -216,7 +216,9 @@ export function SimplicityProgram() {
       });
     } catch (error) {
       const errorMessage = error instanceof Error ? error.message : "Unknown error";
-      addLog(`Failed to compile Simplicity program: ${errorMessage}`);
+      const span = error instanceof CompileDiagnosticError ? error.diagnostic.span : undefined;
+      const location = span ? ` (line ${span.line}, column ${span.column})` : '';
+      addLog(`Failed to compile Simplicity program${location}: ${errorMessage}`);
       showNotification("Failed to compile Simplicity program", true);
       console.error("Simplicity program compilation error:", error);
       
@@ -2,12 +2,16 @@ import { API_CONFIG } from "@/config/api.config";
 import {
   compile as wasmCompile,
   convert_script as wasmConvertScript,
+  build_multisig_template as wasmBuildMultisigTemplate,
+  analyze_script as wasmAnalyzeScript,
   finalize_pset as wasmFinalizePset,
   finalize_psbt as wasmFinalizePsbt,
   sighash_pset as wasmSighashPset,
   sighash_psbt as wasmSighashPsbt,
   create_pset as wasmCreatePset,
   create_psbt as wasmCreatePsbt,
+  witness_abi as wasmWitnessAbi,
+  tweak_key as wasmTweakKey,
 } from "@/simplicity-lib/pkg/simplicity_lib";
 
 /**
@@ -27,36 +31,199 @@ function mapToObject<T>(mapOrObject: unknown): T {
 // Types based on API documentation
 export interface WitnessValue {
   value: string;
-  type: string;
+  type?: string;
 }
 
 export interface CompileRequest {
   script: string;
   include_debug: boolean;
   witness?: Record<string, WitnessValue>;
+  arguments?: Record<string, WitnessValue>;
   environment?: "elements" | "bitcoin";
 }
 
 export interface CompileResponse {
   program_base64: string;
   witness_base64?: string;
+  cmr_hex: string;
+  amr_hex?: string;
+  imr_hex?: string;
+  program_size_bits: number;
+  program_size_bytes: number;
+  witness_size_bits?: number;
+  witness_size_bytes?: number;
+  node_count: number;
+}
+
+export interface CompileDiagnostic {
+  kind: "parse" | "type" | "witness-parse" | "argument-parse" | "satisfy";
+  message: string;
+  span?: {
+    line: number;
+    column: number;
+    end_line: number;
+    end_column: number;
+  };
+  key?: string;
+}
+
+/**
+ * Compile failure carrying the structured diagnostic returned by the WASM compiler
+ */
+export class CompileDiagnosticError extends Error {
+  diagnostic: CompileDiagnostic;
+
+  constructor(diagnostic: CompileDiagnostic) {
+    super(`Compile failed: ${diagnostic.message}`);
+    this.name = "CompileDiagnosticError";
+    this.diagnostic = diagnostic;
+  }
+}
+
+function isCompileDiagnostic(value: unknown): value is CompileDiagnostic {
+  return (
+    typeof value === "object" &&
+    value !== null &&
+    "kind" in value &&
+    "message" in value
+  );
+}
+
+export interface WitnessAbiRequest {
+  script: string;
+  arguments?: Record<string, WitnessValue>;
+  environment?: "elements" | "bitcoin";
+}
+
+export interface AbiEntry {
+  name: string;
+  type: string;
+}
+
+export interface WitnessAbiResponse {
+  witness: AbiEntry[];
+  parameters: AbiEntry[];
 }
 
 export interface ConvertRequest {
   script: string;
   network: string;
+  address_type?: "p2wsh" | "p2sh" | "p2sh-p2wsh" | "p2tr";
+  internal_key?: string;
+  blinding_pubkey?: string;
+  master_blinding_key?: string;
+  syntax?: "opcodes" | "miniscript";
+  keys?: Record<string, string>;
 }
 
 export interface ConvertResponse {
   hex: string;
   address: string;
+  unconfidential_address?: string;
+  blinding_pubkey?: string;
+  redeem_script?: string;
+  witness_script?: string;
+  control_block?: string;
+  leaf_hash?: string;
+  internal_key?: string;
+  warnings?: string[];
+  offset_map: ScriptElement[];
+  descriptor?: string;
+  spending_policy?: string;
+  // Signatures, timelocks and preimages of each spending path; omitted when
+  // there are too many paths to list
+  spending_paths?: string[][];
+  max_satisfaction_weight?: number;
+}
+
+export interface ScriptElement {
+  offset: number;
+  length: number;
+  opcode: string;
+  kind: "opcode" | "pubkey" | "x-only-key" | "number" | "data";
+  data?: string;
+  value?: number;
+}
+
+export interface AnalyzeScriptRequest {
+  script: string;
+  address_type?: ConvertRequest["address_type"];
 }
 
+export interface ScriptIssue {
+  offset?: number;
+  network?: "elements" | "bitcoin";
+  severity: "unspendable" | "non-standard" | "warning";
+  message: string;
+}
+
+export interface AnalyzeScriptResponse {
+  size: number;
+  size_limit?: number;
+  sigops: number;
+  op_count: number;
+  max_stack_depth: number;
+  required_inputs: number;
+  minimal_pushes: boolean;
+  // Whether spends are relayed by default on each network family
+  standard: { elements: boolean; bitcoin: boolean };
+  issues: ScriptIssue[];
+}
+
+export interface MultisigTemplateRequest {
+  su_key: string;
+  user_key: string;
+  recovery_key?: string;
+  recovery_delay?: number;
+  // Tapscript disables OP_CHECKMULTISIG, so p2tr is rejected
+  address_type?: Exclude<ConvertRequest["address_type"], "p2tr">;
+}
+
+export interface MultisigTemplateResponse {
+  hex: string;
+  asm: string;
+  addresses: { network: string; address: string }[];
+  offsets: {
+    su_key: number;
+    user_key: number;
+    recovery_key?: number;
+    recovery_delay?: number;
+  };
+}
+
+// How an input will be signed, for fee estimation
+export type InputSpend =
+  // A template with a recovery path needs its witness_script; recovery selects that path
+  | { type: "multisig2of2"; witness_script?: string; recovery?: boolean }
+  | { type: "simplicity"; program: string; witness: string };
+
 export interface CreatePsetRequest {
   inputs: string[];
+  // "address:value" or "address:value:asset"; asset_id is the default asset
   outputs: string[];
   asset_id: string | null;
   network: string;
+  // With a change address, inputs are a pool to select from and the fee output is added
+  change_address?: string;
+  // With a fee rate the fee output is added, and an "address:change" output takes the rest
+  fee_rate?: number;
+  // Per input, in the same order as `inputs`
+  input_spends?: (InputSpend | null)[];
+  // Private blinding keys (hex) of confidential inputs, in the same order as `inputs`
+  input_blinding_keys?: (string | null)[];
+  // With change_address, the inputs carrying issuances are always selected
+  issuances?: IssuanceRequest[];
+}
+
+// A new issuance, or a reissuance when reissue_entropy is set
+export interface IssuanceRequest {
+  input: number;
+  amount?: number;
+  asset_address?: string;
+  token_amount?: number;
+  token_address?: string;
+  contract_hash?: string;
+  reissue_entropy?: string;
 }
 
 export interface CreatePsetResponse {
@@ -65,6 +232,20 @@ export interface CreatePsetResponse {
   network: string;
   outputs: number;
   pset: string;
+  selected_inputs: string[];
+  fee: number;
+  change?: { asset: string; value: number }[];
+  weight: number;
+  vsize: number;
+  effective_fee_rate: number;
+  // Outputs to confidential addresses, blinded with commitments and proofs
+  blinded_outputs?: number[];
+  issuances?: Array<{
+    input: number;
+    asset: string;
+    token: string;
+    entropy: string;
+  }>;
 }
 
 export interface SighashPsetRequest {
@@ -104,6 +285,12 @@ export interface CreatePsbtRequest {
   inputs: string[];
   outputs: string[];
   network: string;
+  // With a change address, inputs are a pool to select from
+  change_address?: string;
+  // With a fee rate an "address:change" output takes whatever the fee leaves over
+  fee_rate?: number;
+  // Per input, in the same order as `inputs`
+  input_spends?: (InputSpend | null)[];
 }
 
 export interface CreatePsbtResponse {
@@ -111,6 +298,12 @@ export interface CreatePsbtResponse {
   network: string;
   outputs: number;
   psbt: string;
+  selected_inputs: string[];
+  fee: number;
+  change?: number;
+  weight: number;
+  vsize: number;
+  effective_fee_rate: number;
 }
 
 export interface SighashPsbtRequest {
@@ -197,6 +390,20 @@ export interface TweakResponse {
   tweaked_public_key_hex: string;
 }
 
+export interface TweakKeyRequest {
+  // Base64 program, as returned by compile
+  program: string;
+  jet_env: "elements" | "bitcoin";
+  public_key: string;
+}
+
+export interface TweakKeyResponse {
+  cmr_hex: string;
+  tweaked_public_key_hex: string;
+  tweaked_x_only_public_key_hex: string;
+  parity: number;
+}
+
 class ProxyApiService {
   private baseUrl: string;
 
@@ -238,6 +445,25 @@ class ProxyApiService {
     };
   }
 
+  /**
+   * Fetch a raw Liquid transaction, whose outputs carry commitments and range proofs
+   */
+  private async fetchPsetTxHex(txid: string, network: string): Promise<string> {
+    const apiUrl =
+      network === "liquid"
+        ? `https://blockstream.info/liquid/api/tx/${txid}/hex`
+        : `https://blockstream.info/liquidtestnet/api/tx/${txid}/hex`;
+
+    const response = await fetch(apiUrl);
+    if (!response.ok) {
+      throw new Error(
+        `Failed to fetch transaction ${txid}: ${response.statusText}`,
+      );
+    }
+
+    return response.text();
+  }
+
   /**
    * Fetch UTXO data from blockchain explorer for Bitcoin
    */
@@ -318,6 +544,13 @@ class ProxyApiService {
       const result = wasmCompile(request);
       return mapToObject<CompileResponse>(result);
     } catch (error) {
+      const diagnostic = mapToObject<unknown>(error);
+      if (isCompileDiagnostic(diagnostic)) {
+        throw new CompileDiagnosticError(diagnostic);
+      }
+      if (typeof error === "string") {
+        throw new Error(`Compile failed: ${error}`);
+      }
       if (error instanceof Error) {
         throw new Error(`Compile failed: ${error.message}`);
       }
@@ -325,6 +558,28 @@ class ProxyApiService {
     }
   }
 
+  /**
+   * List the witness values and parameters a Simplicity script declares, with their types
+   */
+  async witnessAbi(request: WitnessAbiRequest): Promise<WitnessAbiResponse> {
+    try {
+      const result = wasmWitnessAbi(request);
+      return mapToObject<WitnessAbiResponse>(result);
+    } catch (error) {
+      const diagnostic = mapToObject<unknown>(error);
+      if (isCompileDiagnostic(diagnostic)) {
+        throw new CompileDiagnosticError(diagnostic);
+      }
+      if (typeof error === "string") {
+        throw new Error(`Witness ABI failed: ${error}`);
+      }
+      if (error instanceof Error) {
+        throw new Error(`Witness ABI failed: ${error.message}`);
+      }
+      throw new Error("Witness ABI failed with unknown error");
+    }
+  }
+
   /**
    * Convert human-readable Bitcoin Script opcodes into a hex string
    */
@@ -340,6 +595,62 @@ class ProxyApiService {
     }
   }
 
+  /**
+   * Check a script against size, sigop, stack and opcode limits on Elements and Bitcoin
+   */
+  async analyzeScript(request: AnalyzeScriptRequest): Promise<AnalyzeScriptResponse> {
+    try {
+      const result = wasmAnalyzeScript(request);
+      return mapToObject<AnalyzeScriptResponse>(result);
+    } catch (error) {
+      if (typeof error === "string") {
+        throw new Error(`Analyze failed: ${error}`);
+      }
+      if (error instanceof Error) {
+        throw new Error(`Analyze failed: ${error.message}`);
+      }
+      throw new Error("Analyze failed with unknown error");
+    }
+  }
+
+  /**
+   * Build the Simplicity Unchained 2-of-2 redeem script, optionally with a CSV recovery path
+   */
+  async buildMultisigTemplate(
+    request: MultisigTemplateRequest,
+  ): Promise<MultisigTemplateResponse> {
+    try {
+      const result = wasmBuildMultisigTemplate(request);
+      return mapToObject<MultisigTemplateResponse>(result);
+    } catch (error) {
+      if (typeof error === "string") {
+        throw new Error(`Template failed: ${error}`);
+      }
+      if (error instanceof Error) {
+        throw new Error(`Template failed: ${error.message}`);
+      }
+      throw new Error("Template failed with unknown error");
+    }
+  }
+
+  /**
+   * Tweak a public key with a program's CMR locally, without calling the tweak service
+   */
+  async tweakKey(request: TweakKeyRequest): Promise<TweakKeyResponse> {
+    try {
+      const result = wasmTweakKey(request);
+      return mapToObject<TweakKeyResponse>(result);
+    } catch (error) {
+      if (typeof error === "string") {
+        throw new Error(`Tweak key failed: ${error}`);
+      }
+      if (error instanceof Error) {
+        throw new Error(`Tweak key failed: ${error.message}`);
+      }
+      throw new Error("Tweak key failed with unknown error");
+    }
+  }
+
   /**
    * Create an unsigned Partially Signed Elements Transaction (PSET)
    */
@@ -347,10 +658,17 @@ class ProxyApiService {
     try {
       // Fetch UTXO data for all inputs
       const utxos = await Promise.all(
-        request.inputs.map(async (input) => {
+        request.inputs.map(async (input, i) => {
           const [txid, voutStr] = input.split(":");
           const vout = parseInt(voutStr, 10);
-          return this.fetchPsetUtxo(txid, vout, request.network);
+          const spend = request.input_spends?.[i] ?? undefined;
+          const blindingKey = request.input_blinding_keys?.[i];
+          if (blindingKey) {
+            const txHex = await this.fetchPsetTxHex(txid, request.network);
+            return { tx_hex: txHex, blinding_key: blindingKey, spend };
+          }
+          const utxo = await this.fetchPsetUtxo(txid, vout, request.network);
+          return { ...utxo, spend };
         }),
       );
 
@@ -361,6 +679,9 @@ class ProxyApiService {
         utxos,
         asset_id: request.asset_id,
         network: request.network,
+        change_address: request.change_address,
+        fee_rate: request.fee_rate,
+        issuances: request.issuances ?? [],
       });
       return mapToObject<CreatePsetResponse>(result);
     } catch (error) {
@@ -426,10 +747,11 @@ class ProxyApiService {
     try {
       // Fetch UTXO data for all inputs
       const utxos = await Promise.all(
-        request.inputs.map(async (input) => {
+        request.inputs.map(async (input, i) => {
           const [txid, voutStr] = input.split(":");
           const vout = parseInt(voutStr, 10);
-          return this.fetchPsbtUtxo(txid, vout, request.network);
+          const utxo = await this.fetchPsbtUtxo(txid, vout, request.network);
+          return { ...utxo, spend: request.input_spends?.[i] ?? undefined };
         }),
       );
 
@@ -439,6 +761,8 @@ class ProxyApiService {
         outputs: request.outputs,
         utxos,
         network: request.network,
+        change_address: request.change_address,
+        fee_rate: request.fee_rate,
       });
       return mapToObject<CreatePsbtResponse>(result);
     } catch (error) {
@@ -0,0 +1,619 @@
+use anyhow::Result;
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use elements::opcodes::All;
+
+use crate::converter::{
+    AddressType, Instruction, NetworkFamily, ScriptContext, instructions, opcode_problem,
+    parse_script,
+};
+use crate::interpreter::{MAX_ELEMENT_SIZE, MAX_OPS, MAX_SCRIPT_SIZE, MAX_STACK_SIZE};
+
+/// Largest witness script relayed by default for P2WSH spends.
+const MAX_STANDARD_P2WSH_SCRIPT_SIZE: usize = 3600;
+/// Largest witness stack, besides the script, relayed by default for P2WSH spends.
+const MAX_STANDARD_P2WSH_STACK_ITEMS: usize = 100;
+/// Largest number of sigops relayed by default in a P2SH redeem script.
+const MAX_P2SH_SIGOPS: usize = 15;
+
+#[derive(Deserialize)]
+pub struct AnalyzeRequest {
+    pub script: String,
+    #[serde(default)]
+    pub address_type: AddressType,
+}
+
+/// Size, sigop and stack usage of a script, with everything that stops it
+/// from being relayed or spent.
+#[derive(Debug, Serialize)]
+pub struct ScriptAnalysis {
+    pub size: usize,
+    /// Standard size limit for the address type; `None` if only consensus limits apply.
+    pub size_limit: Option<usize>,
+    pub sigops: usize,
+    pub op_count: usize,
+    /// Upper bound on the combined main and alt stack depth, witness items included.
+    pub max_stack_depth: usize,
+    /// Witness items the script consumes beyond what it pushes itself.
+    pub required_inputs: usize,
+    pub minimal_pushes: bool,
+    pub standard: Standardness,
+    pub issues: Vec<ScriptIssue>,
+}
+
+/// Whether spends are relayed by default on each network family: false if
+/// any issue for that family, or for both, is `non-standard` or `unspendable`.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Standardness {
+    pub elements: bool,
+    pub bitcoin: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScriptIssue {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+    /// The network family the issue applies to, or `None` for both.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkFamily>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    /// Consensus rejects every spend.
+    Unspendable,
+    /// Spends are valid but not relayed by default.
+    NonStandard,
+    Warning,
+}
+
+#[wasm_bindgen]
+pub fn analyze_script(request_json: JsValue) -> Result<JsValue, JsValue> {
+    let request: AnalyzeRequest = serde_wasm_bindgen::from_value(request_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse request: {}", e)))?;
+
+    let result = parse_script(&request.script)
+        .and_then(|script| analyze(script.as_bytes(), request.address_type));
+
+    match result {
+        Ok(analysis) => serde_wasm_bindgen::to_value(&analysis)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e))),
+        Err(e) => Err(JsValue::from_str(&format!("Invalid script: {}", e))),
+    }
+}
+
+/// Checks `script` against the consensus and standardness limits that apply
+/// when it is spent from `address_type`, on both Elements and Bitcoin.
+pub fn analyze(script: &[u8], address_type: AddressType) -> Result<ScriptAnalysis> {
+    let context = address_type.script_context();
+    let instructions = instructions(script)?;
+    let mut issues = Vec::new();
+    let mut issue = |offset: Option<usize>,
+                     network: Option<NetworkFamily>,
+                     severity: Severity,
+                     message: String| {
+        issues.push(ScriptIssue {
+            offset,
+            network,
+            severity,
+            message,
+        });
+    };
+
+    let size_limit = match address_type {
+        AddressType::P2wsh | AddressType::P2shP2wsh => Some(MAX_STANDARD_P2WSH_SCRIPT_SIZE),
+        AddressType::P2sh | AddressType::P2tr => None,
+    };
+    if context != ScriptContext::Tapscript && script.len() > MAX_SCRIPT_SIZE {
+        issue(
+            None,
+            None,
+            Severity::Unspendable,
+            format!(
+                "Script is {} bytes, above the {} byte consensus limit",
+                script.len(),
+                MAX_SCRIPT_SIZE
+            ),
+        );
+    } else if address_type == AddressType::P2sh && script.len() > MAX_ELEMENT_SIZE {
+        issue(
+            None,
+            None,
+            Severity::Unspendable,
+            format!(
+                "P2SH redeem script is {} bytes, above the {} byte push limit",
+                script.len(),
+                MAX_ELEMENT_SIZE
+            ),
+        );
+    } else if let Some(limit) = size_limit.filter(|limit| script.len() > *limit) {
+        issue(
+            None,
+            None,
+            Severity::NonStandard,
+            format!(
+                "Witness script is {} bytes, above the {} byte standard limit",
+                script.len(),
+                limit
+            ),
+        );
+    }
+
+    let mut op_count = 0;
+    let mut minimal_pushes = true;
+    let mut depth = DepthTracker::default();
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        let offset = Some(instruction.offset);
+        let name = instruction.name();
+
+        if let Some(data) = instruction.data {
+            if data.len() > MAX_ELEMENT_SIZE {
+                issue(
+                    offset,
+                    None,
+                    Severity::Unspendable,
+                    format!(
+                        "Push of {} bytes exceeds the {} byte element limit",
+                        data.len(),
+                        MAX_ELEMENT_SIZE
+                    ),
+                );
+            }
+            if !is_minimal_push(instruction) {
+                minimal_pushes = false;
+                issue(
+                    offset,
+                    None,
+                    Severity::NonStandard,
+                    format!(
+                        "{} is not the smallest encoding of this push",
+                        name.as_deref().unwrap_or_default()
+                    ),
+                );
+            }
+        } else if instruction.opcode > 0x60 {
+            op_count += 1;
+        }
+
+        match (instruction.opcode, name.as_deref()) {
+            // OP_NOP1 and OP_NOP4..OP_NOP10 are reserved for upgrades and rejected by policy.
+            (0xb0 | 0xb3..=0xb9, _) => issue(
+                offset,
+                None,
+                Severity::NonStandard,
+                format!("Upgradable NOP 0x{:02x} is rejected by standardness", instruction.opcode),
+            ),
+            (_, None) => issue(
+                offset,
+                None,
+                Severity::Warning,
+                format!("Unknown opcode 0x{:02x} fails the script if executed", instruction.opcode),
+            ),
+            (code, Some(name)) if instruction.data.is_none() => {
+                for family in [NetworkFamily::Elements, NetworkFamily::Bitcoin] {
+                    let Some((problem, fatal)) = opcode_problem(All::from(code), family, context)
+                    else {
+                        continue;
+                    };
+                    let severity = if fatal {
+                        Severity::Unspendable
+                    } else {
+                        Severity::Warning
+                    };
+                    issue(offset, Some(family), severity, format!("{} {}", name, problem));
+                }
+            }
+            _ => {}
+        }
+
+        if matches!(name.as_deref(), Some("OP_ELSE" | "OP_ENDIF")) && depth.branches.is_empty() {
+            issue(
+                offset,
+                None,
+                Severity::Unspendable,
+                format!(
+                    "{} without a matching OP_IF",
+                    name.as_deref().unwrap_or_default()
+                ),
+            );
+        }
+        depth.apply(&instructions, index, name.as_deref());
+    }
+    if !depth.branches.is_empty() {
+        issue(
+            None,
+            None,
+            Severity::Unspendable,
+            format!("{} OP_IF or OP_NOTIF never closed by OP_ENDIF", depth.branches.len()),
+        );
+    }
+
+    let sigops = count_sigops(&instructions);
+    if context != ScriptContext::Tapscript && op_count > MAX_OPS {
+        issue(
+            None,
+            None,
+            Severity::Unspendable,
+            format!("Script has {} opcodes, above the limit of {}", op_count, MAX_OPS),
+        );
+    }
+    if address_type == AddressType::P2sh && sigops > MAX_P2SH_SIGOPS {
+        issue(
+            None,
+            None,
+            Severity::NonStandard,
+            format!("Redeem script has {} sigops, above the standard {}", sigops, MAX_P2SH_SIGOPS),
+        );
+    }
+
+    let max_stack_depth = depth.max_depth();
+    let required_inputs = depth.required_inputs();
+    if max_stack_depth > MAX_STACK_SIZE {
+        issue(
+            None,
+            None,
+            Severity::Unspendable,
+            format!(
+                "Stack may reach {} items, above the limit of {}",
+                max_stack_depth, MAX_STACK_SIZE
+            ),
+        );
+    }
+    if context == ScriptContext::SegwitV0 && required_inputs > MAX_STANDARD_P2WSH_STACK_ITEMS {
+        issue(
+            None,
+            None,
+            Severity::NonStandard,
+            format!(
+                "Script needs {} witness items, above the standard {}",
+                required_inputs, MAX_STANDARD_P2WSH_STACK_ITEMS
+            ),
+        );
+    }
+
+    Ok(ScriptAnalysis {
+        size: script.len(),
+        size_limit,
+        sigops,
+        op_count,
+        max_stack_depth,
+        required_inputs,
+        minimal_pushes,
+        standard: Standardness {
+            elements: is_standard(&issues, NetworkFamily::Elements),
+            bitcoin: is_standard(&issues, NetworkFamily::Bitcoin),
+        },
+        issues,
+    })
+}
+
+/// Whether none of `issues` that apply to `family` stop a spend from being relayed.
+fn is_standard(issues: &[ScriptIssue], family: NetworkFamily) -> bool {
+    issues
+        .iter()
+        .filter(|issue| issue.network.is_none_or(|network| network == family))
+        .all(|issue| issue.severity == Severity::Warning)
+}
+
+/// Whether a push uses the shortest encoding for its data, as MINIMALDATA requires.
+fn is_minimal_push(instruction: &Instruction) -> bool {
+    let Some(data) = instruction.data else {
+        return true;
+    };
+
+    match data.len() {
+        0 => instruction.opcode == 0x00,
+        1 if (1..=16).contains(&data[0]) || data[0] == 0x81 => false,
+        len @ 1..=75 => instruction.opcode as usize == len,
+        76..=255 => instruction.opcode == 0x4c,
+        256..=65535 => instruction.opcode == 0x4d,
+        _ => instruction.opcode == 0x4e,
+    }
+}
+
+/// Counts sigops the way Bitcoin Core does for P2SH and witness scripts:
+/// `OP_CHECKMULTISIG` counts its key count when it directly follows
+/// `OP_1`..`OP_16`, and 20 otherwise. Elements also counts
+/// `OP_CHECKSIGFROMSTACK(VERIFY)` as one sigop each.
+fn count_sigops(instructions: &[Instruction]) -> usize {
+    let mut sigops = 0;
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        match instruction.name().as_deref() {
+            Some(
+                "OP_CHECKSIG"
+                | "OP_CHECKSIGVERIFY"
+                | "OP_CHECKSIGFROMSTACK"
+                | "OP_CHECKSIGFROMSTACKVERIFY",
+            ) => sigops += 1,
+            Some("OP_CHECKMULTISIG" | "OP_CHECKMULTISIGVERIFY") => {
+                sigops += match index.checked_sub(1).map(|i| instructions[i].opcode) {
+                    Some(code @ 0x51..=0x60) => (code - 0x50) as usize,
+                    _ => 20,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    sigops
+}
+
+/// Follows the stack height through every branch of a script, relative to
+/// the height before the script starts.
+#[derive(Default)]
+struct DepthTracker {
+    height: i64,
+    alt: i64,
+    lowest: i64,
+    highest: i64,
+    /// Heights at each open `OP_IF`, and the highest height any finished branch ended at.
+    branches: Vec<(i64, i64, Option<(i64, i64)>)>,
+}
+
+impl DepthTracker {
+    fn apply(&mut self, instructions: &[Instruction], index: usize, name: Option<&str>) {
+        let instruction = &instructions[index];
+        if instruction.data.is_some() {
+            self.pop_push(0, 1);
+            return;
+        }
+
+        match name {
+            Some("OP_IF" | "OP_NOTIF") => {
+                self.pop_push(1, 0);
+                self.branches.push((self.height, self.alt, None));
+            }
+            Some("OP_ELSE") => {
+                let (height, alt) = (self.height, self.alt);
+                if let Some((start, start_alt, ended)) = self.branches.last_mut() {
+                    *ended = Some(highest_end(*ended, (height, alt)));
+                    self.height = *start;
+                    self.alt = *start_alt;
+                }
+            }
+            Some("OP_ENDIF") => {
+                if let Some((_, _, ended)) = self.branches.pop() {
+                    let (height, alt) = highest_end(ended, (self.height, self.alt));
+                    self.height = height;
+                    self.alt = alt;
+                }
+            }
+            Some("OP_TOALTSTACK") => {
+                self.pop_push(1, 0);
+                self.alt += 1;
+            }
+            Some("OP_FROMALTSTACK") => {
+                self.alt -= 1;
+                self.pop_push(0, 1);
+            }
+            Some("OP_CHECKMULTISIG" | "OP_CHECKMULTISIGVERIFY") => {
+                let keys = small_number(instructions, index.checked_sub(1));
+                let sigs = small_number(instructions, index.checked_sub(keys + 2));
+                let pushes = if name == Some("OP_CHECKMULTISIG") { 1 } else { 0 };
+                self.pop_push(keys + sigs + 3, pushes);
+            }
+            Some(name) => {
+                let (pops, pushes) = stack_effect(name, instruction.opcode);
+                self.pop_push(pops, pushes);
+            }
+            None => {}
+        }
+    }
+
+    fn pop_push(&mut self, pops: usize, pushes: usize) {
+        self.height -= pops as i64;
+        self.lowest = self.lowest.min(self.height);
+        self.height += pushes as i64;
+        self.highest = self.highest.max(self.height + self.alt);
+    }
+
+    fn max_depth(&self) -> usize {
+        (self.highest - self.lowest) as usize
+    }
+
+    fn required_inputs(&self) -> usize {
+        (-self.lowest) as usize
+    }
+}
+
+fn highest_end(ended: Option<(i64, i64)>, end: (i64, i64)) -> (i64, i64) {
+    match ended {
+        Some((height, alt)) => (height.max(end.0), alt.max(end.1)),
+        None => end,
+    }
+}
+
+/// Value of an `OP_0`..`OP_16` at `index`, or 0 if there is none.
+fn small_number(instructions: &[Instruction], index: Option<usize>) -> usize {
+    match index.and_then(|i| instructions.get(i)).map(|instruction| instruction.opcode) {
+        Some(code @ 0x51..=0x60) => (code - 0x50) as usize,
+        _ => 0,
+    }
+}
+
+/// Items popped and pushed by an opcode other than pushes, conditionals,
+/// alt stack moves and multisig. Items an opcode only reads count as popped
+/// and pushed back.
+fn stack_effect(name: &str, opcode: u8) -> (usize, usize) {
+    match name {
+        _ if (0x4f..=0x60).contains(&opcode) => (0, 1),
+        "OP_DEPTH" | "OP_PUSHCURRENTINPUTINDEX" | "OP_INSPECTVERSION" | "OP_INSPECTLOCKTIME"
+        | "OP_INSPECTNUMINPUTS" | "OP_INSPECTNUMOUTPUTS" | "OP_TXWEIGHT" => (0, 1),
+        "OP_VERIFY" | "OP_DROP" => (1, 0),
+        "OP_2DROP" => (2, 0),
+        "OP_2DUP" => (2, 4),
+        "OP_3DUP" => (3, 6),
+        "OP_2OVER" => (4, 6),
+        "OP_2ROT" => (6, 6),
+        "OP_2SWAP" => (4, 4),
+        "OP_IFDUP" | "OP_DUP" | "OP_SIZE" => (1, 2),
+        "OP_NIP" => (2, 1),
+        "OP_OVER" => (2, 3),
+        "OP_ROT" => (3, 3),
+        "OP_SWAP" => (2, 2),
+        "OP_TUCK" => (2, 3),
+        // The item moved by OP_ROLL is already on the stack.
+        "OP_PICK" => (1, 1),
+        "OP_ROLL" => (1, 0),
+        "OP_EQUALVERIFY" | "OP_NUMEQUALVERIFY" | "OP_CHECKSIGVERIFY" => (2, 0),
+        "OP_1ADD" | "OP_1SUB" | "OP_NEGATE" | "OP_ABS" | "OP_NOT" | "OP_0NOTEQUAL"
+        | "OP_RIPEMD160" | "OP_SHA1" | "OP_SHA256" | "OP_HASH160" | "OP_HASH256" | "OP_CLTV"
+        | "OP_CSV" | "OP_INVERT" | "OP_SCRIPTNUMTOLE64" | "OP_LE64TOSCRIPTNUM"
+        | "OP_LE32TOLE64" | "OP_SHA256INITIALIZE" | "OP_INSPECTINPUTSEQUENCE"
+        | "OP_INSPECTOUTPUTNONCE" => (1, 1),
+        "OP_WITHIN" | "OP_SUBSTR" => (3, 1),
+        "OP_CHECKSIGFROMSTACK" | "OP_CHECKSIGADD" => (3, 1),
+        "OP_CHECKSIGFROMSTACKVERIFY" | "OP_ECMULSCALARVERIFY" | "OP_TWEAKVERIFY" => (3, 0),
+        "OP_NEG64" | "OP_INSPECTINPUTASSET" | "OP_INSPECTINPUTVALUE"
+        | "OP_INSPECTINPUTSCRIPTPUBKEY" | "OP_INSPECTOUTPUTASSET" | "OP_INSPECTOUTPUTVALUE"
+        | "OP_INSPECTOUTPUTSCRIPTPUBKEY" => (1, 2),
+        "OP_INSPECTINPUTOUTPOINT" => (1, 3),
+        // Issuances push up to four items.
+        "OP_INSPECTINPUTISSUANCE" => (1, 4),
+        "OP_ADD64" | "OP_SUB64" | "OP_MUL64" => (2, 2),
+        "OP_DIV64" => (2, 3),
+        "OP_NOP" | "OP_NOP1" | "OP_NOP4" | "OP_NOP5" | "OP_NOP6" | "OP_NOP7" | "OP_NOP8"
+        | "OP_NOP9" | "OP_NOP10" | "OP_CODESEPARATOR" | "OP_RETURN" => (0, 0),
+        // Everything else, including comparisons, arithmetic, OP_EQUAL,
+        // OP_CHECKSIG and the splice opcodes, takes two items and leaves one.
+        _ => (2, 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sigops(script: &[u8]) -> usize {
+        count_sigops(&instructions(script).unwrap())
+    }
+
+    #[test]
+    fn counts_signature_opcodes() {
+        // OP_CHECKSIG OP_CHECKSIGVERIFY
+        assert_eq!(sigops(&[0xac, 0xad]), 2);
+        // OP_CHECKSIGFROMSTACK OP_CHECKSIGFROMSTACKVERIFY
+        assert_eq!(sigops(&[0xc1, 0xc2]), 2);
+    }
+
+    #[test]
+    fn counts_multisig_keys_only_after_a_small_number() {
+        // OP_3 OP_CHECKMULTISIG
+        assert_eq!(sigops(&[0x53, 0xae]), 3);
+        // OP_DEPTH OP_CHECKMULTISIGVERIFY
+        assert_eq!(sigops(&[0x74, 0xaf]), 20);
+        assert_eq!(sigops(&[0xae]), 20);
+    }
+
+    fn analyze_hex(script: &str) -> ScriptAnalysis {
+        analyze(&hex::decode(script).unwrap(), AddressType::P2wsh).unwrap()
+    }
+
+    fn is_minimal(script: &[u8]) -> bool {
+        is_minimal_push(&instructions(script).unwrap()[0])
+    }
+
+    #[test]
+    fn tracks_depth_through_branches() {
+        // OP_IF OP_1 OP_1 OP_ELSE OP_1 OP_ENDIF
+        let analysis = analyze_hex("635151675168");
+        assert_eq!(analysis.required_inputs, 1);
+        assert_eq!(analysis.max_stack_depth, 2);
+        assert!(analysis.issues.is_empty());
+    }
+
+    #[test]
+    fn reports_unbalanced_conditionals() {
+        // OP_1 OP_ENDIF OP_ELSE
+        let analysis = analyze_hex("516867");
+        let offsets: Vec<_> = analysis.issues.iter().map(|issue| issue.offset).collect();
+        assert_eq!(offsets, [Some(1), Some(2)]);
+        assert!(
+            analysis
+                .issues
+                .iter()
+                .all(|issue| issue.severity == Severity::Unspendable)
+        );
+
+        // OP_1 OP_IF OP_1
+        let analysis = analyze_hex("516351");
+        assert_eq!(analysis.issues.len(), 1);
+        assert!(analysis.issues[0].message.contains("never closed"));
+        assert_eq!(
+            analysis.standard,
+            Standardness {
+                elements: false,
+                bitcoin: false
+            }
+        );
+    }
+
+    #[test]
+    fn limits_standard_witness_script_size() {
+        // Six 517-byte pushes and one of 477 bytes make exactly 3600 bytes.
+        let mut script = Vec::new();
+        for len in [517usize, 517, 517, 517, 517, 517, 477] {
+            script.extend([0x4d, len as u8, (len >> 8) as u8]);
+            script.extend(std::iter::repeat_n(0xab, len));
+        }
+        assert_eq!(script.len(), 3600);
+
+        let analysis = analyze(&script, AddressType::P2wsh).unwrap();
+        assert_eq!(analysis.size_limit, Some(3600));
+        assert!(analysis.issues.is_empty(), "{:?}", analysis.issues);
+
+        script.push(0x61);
+        let analysis = analyze(&script, AddressType::P2wsh).unwrap();
+        assert_eq!(analysis.issues.len(), 1);
+        assert_eq!(analysis.issues[0].severity, Severity::NonStandard);
+        assert!(analysis.issues[0].message.contains("3601 bytes"));
+        assert!(!analysis.standard.elements && !analysis.standard.bitcoin);
+
+        // Legacy P2SH has no such limit, only the 520-byte push limit.
+        assert!(
+            analyze(&script, AddressType::P2sh)
+                .unwrap()
+                .size_limit
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn reports_standardness_per_network() {
+        // OP_1 OP_1 OP_CAT: only enabled in Elements tapscript, disabled on Bitcoin.
+        let analysis = analyze_hex("51517e");
+        assert_eq!(
+            analysis.standard,
+            Standardness {
+                elements: true,
+                bitcoin: false
+            }
+        );
+    }
+
+    #[test]
+    fn recognizes_minimal_pushes() {
+        assert!(is_minimal(&[0x00]));
+        assert!(is_minimal(&[0x01, 0x11]));
+        assert!(is_minimal(&[0x02, 0x90, 0x00]));
+        let mut push_75 = vec![0x4b];
+        push_75.extend([0xab; 75]);
+        assert!(is_minimal(&push_75));
+
+        // Empty data, and 1..=16 and -1, have their own opcodes.
+        assert!(!is_minimal(&[0x4c, 0x00]));
+        assert!(!is_minimal(&[0x01, 0x05]));
+        assert!(!is_minimal(&[0x01, 0x81]));
+        // Up to 75 bytes fit the direct push opcodes.
+        assert!(!is_minimal(&[0x4c, 0x01, 0x11]));
+        let mut pushdata1_75 = vec![0x4c, 0x4b];
+        pushdata1_75.extend([0xab; 75]);
+        assert!(!is_minimal(&pushdata1_75));
+        assert!(!is_minimal(&[0x4d, 0x01, 0x00, 0x11]));
+    }
+}
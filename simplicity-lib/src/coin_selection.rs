@@ -0,0 +1,359 @@
+use anyhow::{Result, anyhow};
+
+use crate::fee::fee_for_weight;
+
+/// Upper bound on the branches explored by branch-and-bound before falling back.
+const BNB_TOTAL_TRIES: usize = 100_000;
+
+/// A UTXO that coin selection may spend.
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate {
+    pub value: u64,
+    /// Weight the input adds to the transaction, witness included.
+    pub weight: u64,
+}
+
+/// What a transaction has to pay for besides its inputs.
+#[derive(Debug, Clone, Copy)]
+pub struct SelectionTarget {
+    /// Sum of the payment outputs.
+    pub amount: u64,
+    /// Value of inputs that are spent whether selected or not.
+    pub pinned_value: u64,
+    /// Weight of the transaction without candidate inputs or change, with
+    /// pinned inputs included.
+    pub base_weight: u64,
+    /// Weight a change output adds.
+    pub change_weight: u64,
+    /// Weight of spending the change output later.
+    pub change_spend_weight: u64,
+    /// Fee rate in sat/vB.
+    pub fee_rate: f64,
+    pub dust_limit: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Selection {
+    /// Indices into the candidate list, in the order they were given.
+    pub indices: Vec<usize>,
+    pub fee: u64,
+    /// Change to send back, `None` when the excess went to the fee.
+    pub change: Option<u64>,
+}
+
+/// Picks inputs to cover `target`.
+///
+/// Branch-and-bound looks for a set that pays the target with no change
+/// output, wasting at most what a change output would cost. If none is found,
+/// the largest candidates are taken until the target and a change output are
+/// covered.
+pub fn select_coins(candidates: &[Candidate], target: &SelectionTarget) -> Result<Selection> {
+    // Checked up front so that the sums below cannot overflow.
+    let available = candidates
+        .iter()
+        .try_fold(0u64, |total, candidate| total.checked_add(candidate.value))
+        .ok_or_else(|| anyhow!("Candidate values overflow"))?;
+    if available.saturating_add(target.pinned_value) < target.amount {
+        return Err(anyhow!(
+            "Insufficient funds: need {} sat plus fees, candidates hold {} sat",
+            target.amount,
+            available.saturating_add(target.pinned_value)
+        ));
+    }
+
+    let effective = |candidate: &Candidate| {
+        candidate.value as i64 - fee_for_weight(candidate.weight, target.fee_rate) as i64
+    };
+
+    let mut by_value: Vec<(usize, i64)> = candidates
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| (i, effective(candidate)))
+        .filter(|(_, value)| *value > 0)
+        .collect();
+    by_value.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let needed = (target.amount + fee_for_weight(target.base_weight, target.fee_rate)) as i64
+        - target.pinned_value as i64;
+    let cost_of_change = fee_for_weight(target.change_weight, target.fee_rate)
+        + fee_for_weight(target.change_spend_weight, target.fee_rate);
+
+    if let Some(mut indices) = branch_and_bound(&by_value, needed, cost_of_change as i64) {
+        indices.sort_unstable();
+        let total: u64 = indices.iter().map(|i| candidates[*i].value).sum();
+        return Ok(Selection {
+            indices,
+            fee: total + target.pinned_value - target.amount,
+            change: None,
+        });
+    }
+
+    largest_first(candidates, &by_value, target)
+}
+
+/// Depth-first search over candidates sorted by descending effective value
+/// for a subset within `[needed, needed + cost_of_change]`, keeping the one
+/// with the least excess.
+fn branch_and_bound(
+    by_value: &[(usize, i64)],
+    needed: i64,
+    cost_of_change: i64,
+) -> Option<Vec<usize>> {
+    let mut remaining: i64 = by_value.iter().map(|(_, value)| value).sum();
+    if remaining < needed {
+        return None;
+    }
+
+    let mut selected = vec![false; by_value.len()];
+    let mut best: Option<(i64, Vec<bool>)> = None;
+    let mut total = 0i64;
+    let mut depth = 0;
+
+    for _ in 0..BNB_TOTAL_TRIES {
+        let backtrack = if total + remaining < needed || total > needed + cost_of_change {
+            true
+        } else if total >= needed {
+            let excess = total - needed;
+            if best
+                .as_ref()
+                .is_none_or(|(best_excess, _)| excess < *best_excess)
+            {
+                best = Some((excess, selected.clone()));
+                if excess == 0 {
+                    break;
+                }
+            }
+            true
+        } else {
+            // Every candidate has been decided without reaching the target.
+            depth == by_value.len()
+        };
+
+        if backtrack {
+            // Walk back to the last included candidate and try omitting it instead.
+            while depth > 0 && !selected[depth - 1] {
+                depth -= 1;
+                remaining += by_value[depth].1;
+            }
+            if depth == 0 {
+                break;
+            }
+            selected[depth - 1] = false;
+            total -= by_value[depth - 1].1;
+            continue;
+        }
+
+        remaining -= by_value[depth].1;
+        selected[depth] = true;
+        total += by_value[depth].1;
+        depth += 1;
+    }
+
+    best.map(|(_, selected)| {
+        selected
+            .iter()
+            .zip(by_value)
+            .filter(|(chosen, _)| **chosen)
+            .map(|(_, (index, _))| *index)
+            .collect()
+    })
+}
+
+fn largest_first(
+    candidates: &[Candidate],
+    by_value: &[(usize, i64)],
+    target: &SelectionTarget,
+) -> Result<Selection> {
+    let mut indices = Vec::new();
+    let mut total = target.pinned_value;
+    let mut weight = target.base_weight;
+    let mut remaining = by_value.iter();
+
+    // Pinned inputs alone may already cover the target and a change output.
+    loop {
+        let fee_with_change = fee_for_weight(weight + target.change_weight, target.fee_rate);
+        let change = total
+            .checked_sub(target.amount + fee_with_change)
+            .filter(|change| *change >= target.dust_limit);
+        if let Some(change) = change {
+            indices.sort_unstable();
+            return Ok(Selection {
+                indices,
+                fee: fee_with_change,
+                change: Some(change),
+            });
+        }
+
+        let Some((index, _)) = remaining.next() else {
+            break;
+        };
+        indices.push(*index);
+        total += candidates[*index].value;
+        weight += candidates[*index].weight;
+    }
+
+    // Not enough for a change output; spend everything if that covers the fee.
+    let fee = fee_for_weight(weight, target.fee_rate);
+    if total >= target.amount + fee {
+        indices.sort_unstable();
+        return Ok(Selection {
+            indices,
+            fee: total - target.amount,
+            change: None,
+        });
+    }
+
+    Err(anyhow!(
+        "Insufficient funds: need {} sat plus fees, candidates hold {} sat",
+        target.amount,
+        candidates
+            .iter()
+            .map(|candidate| candidate.value)
+            .sum::<u64>()
+            + target.pinned_value
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// At 1 sat/vB: 68 sat per input, 100 sat for the rest of the
+    /// transaction and 31 + 68 sat to create and later spend change.
+    fn target(amount: u64) -> SelectionTarget {
+        SelectionTarget {
+            amount,
+            pinned_value: 0,
+            base_weight: 400,
+            change_weight: 124,
+            change_spend_weight: 272,
+            fee_rate: 1.0,
+            dust_limit: 546,
+        }
+    }
+
+    fn candidates(values: &[u64]) -> Vec<Candidate> {
+        values
+            .iter()
+            .map(|value| Candidate {
+                value: *value,
+                weight: 272,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_exact_match_without_change() {
+        let selection =
+            select_coins(&candidates(&[5_068, 5_168, 20_000]), &target(10_000)).unwrap();
+        assert_eq!(
+            selection,
+            Selection {
+                indices: vec![0, 1],
+                fee: 236,
+                change: None,
+            }
+        );
+    }
+
+    #[test]
+    fn leaves_out_change_costing_more_than_the_excess() {
+        // 82 sat over the target, less than the 99 sat a change output costs.
+        let selection = select_coins(&candidates(&[10_250]), &target(10_000)).unwrap();
+        assert_eq!(
+            selection,
+            Selection {
+                indices: vec![0],
+                fee: 250,
+                change: None,
+            }
+        );
+    }
+
+    #[test]
+    fn falls_back_to_largest_first_with_change() {
+        let selection =
+            select_coins(&candidates(&[3_000, 50_000, 4_000]), &target(10_000)).unwrap();
+        assert_eq!(
+            selection,
+            Selection {
+                indices: vec![1],
+                fee: 199,
+                change: Some(39_801),
+            }
+        );
+    }
+
+    #[test]
+    fn gives_change_below_dust_to_the_fee() {
+        // Change would be 301 sat: too much for branch-and-bound, below dust.
+        let selection = select_coins(&candidates(&[10_500]), &target(10_000)).unwrap();
+        assert_eq!(
+            selection,
+            Selection {
+                indices: vec![0],
+                fee: 500,
+                change: None,
+            }
+        );
+    }
+
+    #[test]
+    fn counts_pinned_inputs_towards_the_target() {
+        let pinned = |pinned_value| SelectionTarget {
+            pinned_value,
+            ..target(10_000)
+        };
+
+        // Pinned inputs covering the target and the base fee exactly.
+        let selection = select_coins(&candidates(&[50_000]), &pinned(10_100)).unwrap();
+        assert_eq!(
+            selection,
+            Selection {
+                indices: vec![],
+                fee: 100,
+                change: None,
+            }
+        );
+
+        // The surplus of pinned inputs goes to change without selecting more.
+        let selection = select_coins(&candidates(&[50_000]), &pinned(20_000)).unwrap();
+        assert_eq!(
+            selection,
+            Selection {
+                indices: vec![],
+                fee: 131,
+                change: Some(9_869),
+            }
+        );
+
+        // Candidates make up the rest.
+        let selection = select_coins(&candidates(&[5_168, 20_000]), &pinned(5_000)).unwrap();
+        assert_eq!(
+            selection,
+            Selection {
+                indices: vec![0],
+                fee: 168,
+                change: None,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_insufficient_funds() {
+        let error = select_coins(&candidates(&[5_000, 4_000]), &target(10_000)).unwrap_err();
+        assert!(
+            error.to_string().contains("Insufficient funds"),
+            "{}",
+            error
+        );
+
+        // Enough for the outputs but not for the fee.
+        let error = select_coins(&candidates(&[10_050]), &target(10_000)).unwrap_err();
+        assert!(
+            error.to_string().contains("Insufficient funds"),
+            "{}",
+            error
+        );
+    }
+}
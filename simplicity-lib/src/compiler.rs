@@ -1,13 +1,21 @@
 use std::collections::HashMap;
+use std::io;
+use anyhow::{Context, Result};
 use wasm_bindgen::prelude::*;
 use serde::{Deserialize, Serialize};
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
+use elements::bitcoin::hashes::Hash;
+use elements::bitcoin::secp256k1::{Parity, PublicKey, Secp256k1, XOnlyPublicKey};
+use elements::bitcoin::taproot::{TapNodeHash, TapTweakHash};
 use simplicityhl::parse::ParseFromStr;
+use simplicityhl::simplicity::dag::InternalSharing;
+use simplicityhl::simplicity::jet::Jet;
+use simplicityhl::simplicity::{BitIter, BitWriter, Cmr, CommitNode, RedeemNode, encode};
 use simplicityhl::simplicity_unchained::jets::bitcoin::CoreExtension;
 use simplicityhl::simplicity_unchained::jets::elements::ElementsExtension;
 use simplicityhl::str::WitnessName;
-use simplicityhl::{ResolvedType, Value, WitnessValues};
+use simplicityhl::{ResolvedType, Value, WitnessTypes, WitnessValues};
 
 #[derive(Debug, Deserialize, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
@@ -20,6 +28,9 @@ pub enum Environment {
 pub struct CompileRequest {
     pub script: String,
     pub witness: Option<HashMap<String, Witness>>,
+    /// Values for the program's `param::NAME` parameters, in the same shape as the witness.
+    #[serde(default)]
+    pub arguments: HashMap<String, Witness>,
     #[serde(default)]
     pub include_debug: bool,
     #[serde(default = "default_environment")]
@@ -35,15 +46,218 @@ pub struct CompileResponse {
     pub program_base64: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub witness_base64: Option<String>,
+    pub cmr_hex: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amr_hex: Option<String>,
+    /// Identity Merkle root of the satisfied program (`Ihr` in rust-simplicity).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub imr_hex: Option<String>,
+    pub program_size_bits: usize,
+    pub program_size_bytes: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub witness_size_bits: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub witness_size_bytes: Option<usize>,
+    pub node_count: usize,
+}
+
+/// Roots and encoded size of a satisfied program.
+struct RedeemMetadata {
+    witness_bytes: Vec<u8>,
+    witness_bits: usize,
+    amr_hex: String,
+    imr_hex: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TweakRequest {
+    pub program: String,
+    pub jet_env: Environment,
+    pub public_key: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TweakResponse {
+    pub cmr_hex: String,
+    /// Compressed form of the output key, for use in redeem scripts.
+    pub tweaked_public_key_hex: String,
+    pub tweaked_x_only_public_key_hex: String,
+    /// 1 when the output key has odd y.
+    pub parity: u8,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Witness {
     pub value: String,
+    /// Optional; when omitted the type declared by the program is used.
+    #[serde(rename = "type", default)]
+    pub type_: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WitnessAbiRequest {
+    pub script: String,
+    #[serde(default)]
+    pub arguments: HashMap<String, Witness>,
+    #[serde(default = "default_environment")]
+    pub environment: Environment,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AbiEntry {
+    pub name: String,
     #[serde(rename = "type")]
     pub type_: String,
 }
 
+#[derive(Debug, Serialize)]
+pub struct WitnessAbiResponse {
+    pub witness: Vec<AbiEntry>,
+    pub parameters: Vec<AbiEntry>,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DiagnosticKind {
+    Parse,
+    Type,
+    WitnessParse,
+    ArgumentParse,
+    Satisfy,
+}
+
+/// 1-based line/column range in the program source.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct SourceSpan {
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+/// Compile failure returned to JS as an object instead of a flat string.
+#[derive(Debug, Serialize)]
+pub struct CompileError {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span: Option<SourceSpan>,
+    /// Witness or argument name whose value failed to parse.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+impl CompileError {
+    /// Classifies an error from `TemplateProgram::new` and extracts its span.
+    ///
+    /// SimplicityHL only hands back the rendered diagnostic, so the program is
+    /// re-parsed to tell syntax errors apart from type errors.
+    fn from_compile(script: &str, rendered: String) -> Self {
+        let kind = if simplicityhl::parse::Program::parse_from_str(script).is_err() {
+            DiagnosticKind::Parse
+        } else {
+            DiagnosticKind::Type
+        };
+
+        Self::from_rendered(kind, rendered)
+    }
+
+    fn from_rendered(kind: DiagnosticKind, rendered: String) -> Self {
+        let (span, message) = split_rendered_error(&rendered);
+
+        CompileError {
+            kind,
+            message,
+            span,
+            key: None,
+        }
+    }
+
+    fn value(kind: DiagnosticKind, key: &str, message: String) -> Self {
+        CompileError {
+            kind,
+            message,
+            span: None,
+            key: Some(key.to_string()),
+        }
+    }
+}
+
+impl From<CompileError> for JsValue {
+    fn from(error: CompileError) -> Self {
+        serde_wasm_bindgen::to_value(&error)
+            .unwrap_or_else(|_| JsValue::from_str(&error.message))
+    }
+}
+
+/// Splits a rendered SimplicityHL error into its source span and the bare message.
+///
+/// The renderer prints the offending lines as `N | source` followed by a
+/// `  |   ^^^ message` marker line, where the message may continue on the
+/// lines after it. The marker only underlines the first line of a multi-line
+/// span, so such a span is taken to end with the last line printed.
+fn split_rendered_error(rendered: &str) -> (Option<SourceSpan>, String) {
+    let mut line_numbers = Vec::new();
+    let mut last_line_len = 0;
+    let mut marker: Option<(usize, usize)> = None;
+    let mut message_lines = Vec::new();
+
+    for line in rendered.lines() {
+        let Some((gutter, rest)) = line.split_once('|') else {
+            message_lines.push(line);
+            continue;
+        };
+
+        let gutter = gutter.trim();
+        if let Ok(number) = gutter.parse::<usize>() {
+            line_numbers.push(number);
+            last_line_len = rest.strip_prefix(' ').unwrap_or(rest).chars().count();
+        } else if gutter.is_empty() {
+            if let Some(start) = rest.find('^') {
+                // Columns count characters; `rest` keeps the space after `|`,
+                // which makes the index 1-based.
+                let column = rest[..start].chars().count();
+                let underline = &rest[start..];
+                let width = underline.chars().take_while(|c| *c == '^').count();
+                marker = Some((column, width));
+
+                let message = underline.trim_start_matches('^').trim();
+                if !message.is_empty() {
+                    message_lines.push(message);
+                }
+            }
+        } else {
+            message_lines.push(line);
+        }
+    }
+
+    let message = message_lines.join("\n").trim().to_string();
+
+    let span = match (line_numbers.first(), line_numbers.last()) {
+        (Some(&line), Some(&end_line)) => {
+            let (column, width) = marker.unwrap_or((1, 0));
+            let end_column = if end_line > line {
+                last_line_len + 1
+            } else {
+                column.max(1) + width
+            };
+            Some(SourceSpan {
+                line,
+                column: column.max(1),
+                end_line,
+                end_column,
+            })
+        }
+        _ => None,
+    };
+
+    if message.is_empty() {
+        (span, rendered.to_string())
+    } else {
+        (span, message)
+    }
+}
+
 #[wasm_bindgen]
 pub fn compile(request_json: JsValue) -> Result<JsValue, JsValue> {
     let req: CompileRequest = serde_wasm_bindgen::from_value(request_json)
@@ -59,45 +273,55 @@ fn compile_with_elements(req: CompileRequest) -> Result<JsValue, JsValue> {
     let script = req.script;
     let include_debug = req.include_debug;
 
-    let args = simplicityhl::Arguments::<ElementsExtension>::default();
-
-    let compiled = simplicityhl::CompiledProgram::<ElementsExtension>::new(script, args, include_debug)
-        .map_err(|e| JsValue::from_str(&format!("compile error: {}", e)))?;
+    let template = simplicityhl::TemplateProgram::<ElementsExtension>::new(script.clone())
+        .map_err(|e| CompileError::from_compile(&script, e))?;
 
-    let program_bytes = compiled.commit().to_vec_without_witness();
-    let program_b64 = STANDARD.encode(&program_bytes);
-
-    let witness_b64 = if let Some(witness) = req.witness {
-        let mut converted_witness = HashMap::new();
+    let args = simplicityhl::Arguments::<ElementsExtension>::from(parse_typed_values(
+        req.arguments,
+        &declared_types(template.parameters().iter()),
+        DiagnosticKind::ArgumentParse,
+    )?);
 
-        for (key, value) in witness {
-            let name = WitnessName::from_str_unchecked(key.as_str());
-            let value = Value::parse_from_str(
-                &value.value,
-                &ResolvedType::parse_from_str(value.type_.as_str())
-                    .map_err(|e| JsValue::from_str(&format!("value of witness is incorrect: {}", e)))?,
-            )
-            .map_err(|e| JsValue::from_str(&format!("witness is incorrect: {}", e)))?;
+    let compiled = template
+        .instantiate(args, include_debug)
+        .map_err(|e| CompileError::from_rendered(DiagnosticKind::ArgumentParse, e))?;
 
-            converted_witness.insert(name, value);
-        }
+    let commit = compiled.commit();
+    let (program_bytes, program_bits) = encode_commit(&commit)
+        .map_err(|e| JsValue::from_str(&format!("Failed to encode program: {}", e)))?;
+    let program_b64 = STANDARD.encode(&program_bytes);
 
-        let witness = WitnessValues::from(converted_witness);
+    let redeem = if let Some(witness) = req.witness {
+        check_witness_keys(&witness, compiled.witness_types())?;
+        let witness = WitnessValues::from(parse_typed_values(
+            witness,
+            &declared_types(compiled.witness_types().iter()),
+            DiagnosticKind::WitnessParse,
+        )?);
 
         let satisfied = compiled
             .satisfy(witness)
-            .map_err(|e| JsValue::from_str(&format!("satisfy error: {}", e)))?;
+            .map_err(|e| CompileError::from_rendered(DiagnosticKind::Satisfy, e))?;
 
-        let (_, witness_bytes) = satisfied.redeem().to_vec_with_witness();
+        let metadata = redeem_metadata(satisfied.redeem())
+            .map_err(|e| JsValue::from_str(&format!("Failed to encode witness: {}", e)))?;
 
-        Some(STANDARD.encode(&witness_bytes))
+        Some(metadata)
     } else {
         None
     };
 
     let response = CompileResponse {
         program_base64: program_b64,
-        witness_base64: witness_b64,
+        witness_base64: redeem.as_ref().map(|r| STANDARD.encode(&r.witness_bytes)),
+        cmr_hex: hex::encode(commit.cmr().to_byte_array()),
+        amr_hex: redeem.as_ref().map(|r| r.amr_hex.clone()),
+        imr_hex: redeem.as_ref().map(|r| r.imr_hex.clone()),
+        program_size_bits: program_bits,
+        program_size_bytes: program_bytes.len(),
+        witness_size_bits: redeem.as_ref().map(|r| r.witness_bits),
+        witness_size_bytes: redeem.as_ref().map(|r| r.witness_bytes.len()),
+        node_count: commit.post_order_iter::<InternalSharing>().count(),
     };
 
     serde_wasm_bindgen::to_value(&response)
@@ -108,47 +332,585 @@ fn compile_with_bitcoin(req: CompileRequest) -> Result<JsValue, JsValue> {
     let script = req.script;
     let include_debug = req.include_debug;
 
-    let args = simplicityhl::Arguments::<CoreExtension>::default();
-
-    let compiled = simplicityhl::CompiledProgram::<CoreExtension>::new(script, args, include_debug)
-        .map_err(|e| JsValue::from_str(&format!("compile error: {}", e)))?;
+    let template = simplicityhl::TemplateProgram::<CoreExtension>::new(script.clone())
+        .map_err(|e| CompileError::from_compile(&script, e))?;
 
-    let program_bytes = compiled.commit().to_vec_without_witness();
-    let program_b64 = STANDARD.encode(&program_bytes);
-
-    let witness_b64 = if let Some(witness) = req.witness {
-        let mut converted_witness = HashMap::new();
+    let args = simplicityhl::Arguments::<CoreExtension>::from(parse_typed_values(
+        req.arguments,
+        &declared_types(template.parameters().iter()),
+        DiagnosticKind::ArgumentParse,
+    )?);
 
-        for (key, value) in witness {
-            let name = WitnessName::from_str_unchecked(key.as_str());
-            let value = Value::parse_from_str(
-                &value.value,
-                &ResolvedType::parse_from_str(value.type_.as_str())
-                    .map_err(|e| JsValue::from_str(&format!("value of witness is incorrect: {}", e)))?,
-            )
-            .map_err(|e| JsValue::from_str(&format!("witness is incorrect: {}", e)))?;
+    let compiled = template
+        .instantiate(args, include_debug)
+        .map_err(|e| CompileError::from_rendered(DiagnosticKind::ArgumentParse, e))?;
 
-            converted_witness.insert(name, value);
-        }
+    let commit = compiled.commit();
+    let (program_bytes, program_bits) = encode_commit(&commit)
+        .map_err(|e| JsValue::from_str(&format!("Failed to encode program: {}", e)))?;
+    let program_b64 = STANDARD.encode(&program_bytes);
 
-        let witness = WitnessValues::from(converted_witness);
+    let redeem = if let Some(witness) = req.witness {
+        check_witness_keys(&witness, compiled.witness_types())?;
+        let witness = WitnessValues::from(parse_typed_values(
+            witness,
+            &declared_types(compiled.witness_types().iter()),
+            DiagnosticKind::WitnessParse,
+        )?);
 
         let satisfied = compiled
             .satisfy(witness)
-            .map_err(|e| JsValue::from_str(&format!("satisfy error: {}", e)))?;
+            .map_err(|e| CompileError::from_rendered(DiagnosticKind::Satisfy, e))?;
 
-        let (_, witness_bytes) = satisfied.redeem().to_vec_with_witness();
+        let metadata = redeem_metadata(satisfied.redeem())
+            .map_err(|e| JsValue::from_str(&format!("Failed to encode witness: {}", e)))?;
 
-        Some(STANDARD.encode(&witness_bytes))
+        Some(metadata)
     } else {
         None
     };
 
     let response = CompileResponse {
         program_base64: program_b64,
-        witness_base64: witness_b64,
+        witness_base64: redeem.as_ref().map(|r| STANDARD.encode(&r.witness_bytes)),
+        cmr_hex: hex::encode(commit.cmr().to_byte_array()),
+        amr_hex: redeem.as_ref().map(|r| r.amr_hex.clone()),
+        imr_hex: redeem.as_ref().map(|r| r.imr_hex.clone()),
+        program_size_bits: program_bits,
+        program_size_bytes: program_bytes.len(),
+        witness_size_bits: redeem.as_ref().map(|r| r.witness_bits),
+        witness_size_bytes: redeem.as_ref().map(|r| r.witness_bytes.len()),
+        node_count: commit.post_order_iter::<InternalSharing>().count(),
     };
 
     serde_wasm_bindgen::to_value(&response)
         .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e)))
 }
+
+/// Rejects witness maps that miss a declared `witness::NAME` or carry names the program never uses.
+fn check_witness_keys(
+    witness: &HashMap<String, Witness>,
+    witness_types: &WitnessTypes,
+) -> Result<(), CompileError> {
+    let mut declared: Vec<String> = witness_types.iter().map(|(name, _)| name.to_string()).collect();
+    declared.sort();
+
+    if let Some(missing) = declared.iter().find(|name| !witness.contains_key(*name)) {
+        return Err(CompileError::value(
+            DiagnosticKind::WitnessParse,
+            missing,
+            format!("witness `{}` is declared by the program but has no value", missing),
+        ));
+    }
+
+    let mut provided: Vec<&String> = witness.keys().collect();
+    provided.sort();
+
+    if let Some(extra) = provided.into_iter().find(|name| !declared.contains(*name)) {
+        return Err(CompileError::value(
+            DiagnosticKind::WitnessParse,
+            extra,
+            format!("witness `{}` is not used by the program", extra),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Collects the types a program declares for its witnesses or parameters, keyed by name.
+fn declared_types<'a>(
+    entries: impl Iterator<Item = (&'a WitnessName, &'a ResolvedType)>,
+) -> HashMap<String, ResolvedType> {
+    entries
+        .map(|(name, ty)| (name.to_string(), ty.clone()))
+        .collect()
+}
+
+/// Parses `{value, type}` entries into SimplicityHL values keyed by name.
+///
+/// Entries without a `type` are parsed against the type in `declared`; entries
+/// with one must agree with it. `kind` tells whether witness values or
+/// parameter arguments are being parsed.
+fn parse_typed_values(
+    values: HashMap<String, Witness>,
+    declared: &HashMap<String, ResolvedType>,
+    kind: DiagnosticKind,
+) -> Result<HashMap<WitnessName, Value>, CompileError> {
+    let mut converted = HashMap::new();
+
+    for (key, value) in values {
+        let declared_ty = declared.get(&key);
+
+        let ty = match (value.type_.as_deref(), declared_ty) {
+            (Some(type_str), _) => {
+                let ty = ResolvedType::parse_from_str(type_str).map_err(|e| {
+                    CompileError::value(kind, &key, format!("type of `{}` is incorrect: {}", key, e))
+                })?;
+
+                if let Some(declared_ty) = declared_ty {
+                    if &ty != declared_ty {
+                        return Err(CompileError::value(
+                            kind,
+                            &key,
+                            format!(
+                                "type `{}` given for `{}` does not match the declared type `{}`",
+                                type_str, key, declared_ty
+                            ),
+                        ));
+                    }
+                }
+
+                ty
+            }
+            (None, Some(declared_ty)) => declared_ty.clone(),
+            (None, None) => {
+                return Err(CompileError::value(
+                    kind,
+                    &key,
+                    format!("`{}` is not declared by the program, so its type must be given", key),
+                ));
+            }
+        };
+
+        let parsed = Value::parse_from_str(&value.value, &ty).map_err(|e| {
+            CompileError::value(kind, &key, format!("value of `{}` is incorrect: {}", key, e))
+        })?;
+
+        converted.insert(WitnessName::from_str_unchecked(key.as_str()), parsed);
+    }
+
+    Ok(converted)
+}
+
+#[wasm_bindgen]
+pub fn witness_abi(request_json: JsValue) -> Result<JsValue, JsValue> {
+    let req: WitnessAbiRequest = serde_wasm_bindgen::from_value(request_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse request: {}", e)))?;
+
+    let response = match req.environment {
+        Environment::Elements => witness_abi_with_elements(req)?,
+        Environment::Bitcoin => witness_abi_with_bitcoin(req)?,
+    };
+
+    serde_wasm_bindgen::to_value(&response)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e)))
+}
+
+fn witness_abi_with_elements(req: WitnessAbiRequest) -> Result<WitnessAbiResponse, CompileError> {
+    let template = simplicityhl::TemplateProgram::<ElementsExtension>::new(req.script.clone())
+        .map_err(|e| CompileError::from_compile(&req.script, e))?;
+    let parameters = abi_entries(template.parameters().iter());
+
+    let args = simplicityhl::Arguments::<ElementsExtension>::from(parse_typed_values(
+        req.arguments,
+        &declared_types(template.parameters().iter()),
+        DiagnosticKind::ArgumentParse,
+    )?);
+    let compiled = template
+        .instantiate(args, false)
+        .map_err(|e| CompileError::from_rendered(DiagnosticKind::ArgumentParse, e))?;
+
+    Ok(WitnessAbiResponse {
+        witness: abi_entries(compiled.witness_types().iter()),
+        parameters,
+    })
+}
+
+fn witness_abi_with_bitcoin(req: WitnessAbiRequest) -> Result<WitnessAbiResponse, CompileError> {
+    let template = simplicityhl::TemplateProgram::<CoreExtension>::new(req.script.clone())
+        .map_err(|e| CompileError::from_compile(&req.script, e))?;
+    let parameters = abi_entries(template.parameters().iter());
+
+    let args = simplicityhl::Arguments::<CoreExtension>::from(parse_typed_values(
+        req.arguments,
+        &declared_types(template.parameters().iter()),
+        DiagnosticKind::ArgumentParse,
+    )?);
+    let compiled = template
+        .instantiate(args, false)
+        .map_err(|e| CompileError::from_rendered(DiagnosticKind::ArgumentParse, e))?;
+
+    Ok(WitnessAbiResponse {
+        witness: abi_entries(compiled.witness_types().iter()),
+        parameters,
+    })
+}
+
+/// Lists name/type pairs sorted by name, with types in the syntax `ResolvedType` parses back.
+fn abi_entries<'a>(entries: impl Iterator<Item = (&'a WitnessName, &'a ResolvedType)>) -> Vec<AbiEntry> {
+    let mut entries: Vec<AbiEntry> = entries
+        .map(|(name, ty)| AbiEntry {
+            name: name.to_string(),
+            type_: ty.to_string(),
+        })
+        .collect();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    entries
+}
+
+/// Encodes a program without its witness, returning the bytes and the number of bits written.
+fn encode_commit<J: Jet>(program: &CommitNode<J>) -> io::Result<(Vec<u8>, usize)> {
+    let mut program_bytes = Vec::new();
+    let program_bits = {
+        let mut writer = BitWriter::new(&mut program_bytes);
+        let bits = encode::encode_program(program, &mut writer)?;
+        writer.flush_all()?;
+        bits
+    };
+
+    Ok((program_bytes, program_bits))
+}
+
+fn redeem_metadata<J: Jet>(program: &RedeemNode<J>) -> io::Result<RedeemMetadata> {
+    let mut program_bytes = Vec::new();
+    let mut witness_bytes = Vec::new();
+    let total_bits = program.encode_with_witness(&mut program_bytes, &mut witness_bytes)?;
+    let program_bits = encode::encode_program(program, &mut BitWriter::new(io::sink()))?;
+
+    Ok(RedeemMetadata {
+        witness_bytes,
+        witness_bits: total_bits - program_bits,
+        amr_hex: hex::encode(program.amr().to_byte_array()),
+        imr_hex: hex::encode(program.ihr().to_byte_array()),
+    })
+}
+
+#[wasm_bindgen]
+pub fn tweak_key(request_json: JsValue) -> Result<JsValue, JsValue> {
+    let req: TweakRequest = serde_wasm_bindgen::from_value(request_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse request: {}", e)))?;
+
+    let response = tweak(&req.program, req.jet_env, &req.public_key)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    serde_wasm_bindgen::to_value(&response)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e)))
+}
+
+/// Computes the CMR of a compiled program and tweaks `public_key_hex` with it,
+/// following the same rule as the `/simplicity-unchained/tweak` service:
+/// `Q = lift_x(x(P)) + TaggedHash("TapTweak", x(P) || cmr) * G`, as in BIP341.
+pub fn tweak(program_base64: &str, jet_env: Environment, public_key_hex: &str) -> Result<TweakResponse> {
+    let cmr = match jet_env {
+        Environment::Elements => program_cmr::<ElementsExtension>(program_base64)?,
+        Environment::Bitcoin => program_cmr::<CoreExtension>(program_base64)?,
+    };
+
+    let public_key_bytes = hex::decode(public_key_hex).context("Failed to decode public key hex")?;
+    let public_key = PublicKey::from_slice(&public_key_bytes).context("Invalid public key")?;
+
+    let merkle_root = TapNodeHash::from_byte_array(cmr.to_byte_array());
+    let (output_key, parity) = taproot_output_key(&public_key, merkle_root)?;
+    let tweaked_public_key = PublicKey::from_x_only_public_key(output_key, parity);
+
+    Ok(TweakResponse {
+        cmr_hex: hex::encode(cmr.to_byte_array()),
+        tweaked_public_key_hex: hex::encode(tweaked_public_key.serialize()),
+        tweaked_x_only_public_key_hex: hex::encode(output_key.serialize()),
+        parity: parity.to_u8(),
+    })
+}
+
+/// BIP341 output key committing to `merkle_root`. Only the x coordinate of
+/// `public_key` is used: the tweak applies to its even-y lift.
+fn taproot_output_key(
+    public_key: &PublicKey,
+    merkle_root: TapNodeHash,
+) -> Result<(XOnlyPublicKey, Parity)> {
+    let (internal_key, _) = public_key.x_only_public_key();
+    let tweak = TapTweakHash::from_key_and_tweak(internal_key, Some(merkle_root)).to_scalar();
+
+    let secp = Secp256k1::verification_only();
+    internal_key
+        .add_tweak(&secp, &tweak)
+        .context("Failed to tweak public key")
+}
+
+fn program_cmr<J: Jet>(program_base64: &str) -> Result<Cmr> {
+    let program_bytes = STANDARD
+        .decode(program_base64)
+        .context("Failed to decode program base64")?;
+
+    let program = CommitNode::<J>::decode(BitIter::from(program_bytes.as_slice()))
+        .map_err(|e| anyhow::anyhow!("Failed to decode program: {}", e))?;
+
+    Ok(program.cmr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    // BIP341 wallet test vector `scriptPubKey[1]`, a single-leaf tree whose
+    // output key has odd y.
+    const INTERNAL_KEY: &str = "187791b6f712a8ea41c8ecdd0ee77fab3e85263b37e1ec18a3651926b3a6cf27";
+    const MERKLE_ROOT: &str = "5b75adecf53548f3ec6ad7d78383bf84cc57b55a3127c72b9a2481752dd88b21";
+    const OUTPUT_KEY: &str = "147c9c57132f6e7ecddba9800bb0c4449251c92a1e60371ee77557b6620f3ea3";
+
+    fn merkle_root() -> TapNodeHash {
+        let bytes: [u8; 32] = hex::decode(MERKLE_ROOT).unwrap().try_into().unwrap();
+        TapNodeHash::from_byte_array(bytes)
+    }
+
+    #[test]
+    fn output_key_matches_bip341_vector() {
+        let public_key = PublicKey::from_str(&format!("02{}", INTERNAL_KEY)).unwrap();
+        let (output_key, parity) = taproot_output_key(&public_key, merkle_root()).unwrap();
+
+        assert_eq!(hex::encode(output_key.serialize()), OUTPUT_KEY);
+        assert_eq!(parity, Parity::Odd);
+    }
+
+    #[test]
+    fn output_key_ignores_internal_key_parity() {
+        let even = PublicKey::from_str(&format!("02{}", INTERNAL_KEY)).unwrap();
+        let odd = PublicKey::from_str(&format!("03{}", INTERNAL_KEY)).unwrap();
+
+        assert_eq!(
+            taproot_output_key(&even, merkle_root()).unwrap(),
+            taproot_output_key(&odd, merkle_root()).unwrap()
+        );
+    }
+
+    /// Checks `x` against a bound set when the program is instantiated.
+    const BOUNDED: &str = "fn main() {
+    let x: u32 = witness::X;
+    let flag: bool = witness::FLAG;
+    let limit: u32 = param::LIMIT;
+    assert!(jet::le_32(x, limit));
+    assert!(flag);
+}
+";
+
+    fn untyped(value: &str) -> Witness {
+        Witness {
+            value: value.to_string(),
+            type_: None,
+        }
+    }
+
+    fn abi_request(arguments: Vec<(&str, Witness)>) -> WitnessAbiRequest {
+        WitnessAbiRequest {
+            script: BOUNDED.to_string(),
+            arguments: arguments
+                .into_iter()
+                .map(|(name, witness)| (name.to_string(), witness))
+                .collect(),
+            environment: Environment::Elements,
+        }
+    }
+
+    #[test]
+    fn instantiates_parameters_from_arguments() {
+        let abi = witness_abi_with_elements(abi_request(vec![("LIMIT", untyped("10"))])).unwrap();
+        let parameters: Vec<_> = abi
+            .parameters
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.type_.as_str()))
+            .collect();
+        assert_eq!(parameters, [("LIMIT", "u32")]);
+
+        let error = witness_abi_with_elements(abi_request(Vec::new())).unwrap_err();
+        assert_eq!(error.kind, DiagnosticKind::ArgumentParse);
+
+        let error =
+            witness_abi_with_elements(abi_request(vec![("LIMIT", untyped("ten"))])).unwrap_err();
+        assert_eq!(error.kind, DiagnosticKind::ArgumentParse);
+        assert_eq!(error.key.as_deref(), Some("LIMIT"));
+    }
+
+    #[test]
+    fn lists_witnesses_with_their_types() {
+        let abi = witness_abi_with_elements(abi_request(vec![("LIMIT", untyped("10"))])).unwrap();
+        let witness: Vec<_> = abi
+            .witness
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.type_.as_str()))
+            .collect();
+        assert_eq!(witness, [("FLAG", "bool"), ("X", "u32")]);
+
+        // The types are written so that they parse back.
+        for entry in &abi.witness {
+            assert!(ResolvedType::parse_from_str(&entry.type_).is_ok());
+        }
+    }
+
+    fn typed(value: &str, type_: &str) -> Witness {
+        Witness {
+            value: value.to_string(),
+            type_: Some(type_.to_string()),
+        }
+    }
+
+    fn u32_type() -> ResolvedType {
+        ResolvedType::parse_from_str("u32").unwrap()
+    }
+
+    fn parse_one(name: &str, witness: Witness) -> Result<Value, CompileError> {
+        let declared = HashMap::from([("X".to_string(), u32_type())]);
+        let values = HashMap::from([(name.to_string(), witness)]);
+        let mut parsed = parse_typed_values(values, &declared, DiagnosticKind::WitnessParse)?;
+        Ok(parsed
+            .remove(&WitnessName::from_str_unchecked(name))
+            .unwrap())
+    }
+
+    #[test]
+    fn infers_declared_types() {
+        let seven = Value::parse_from_str("7", &u32_type()).unwrap();
+        assert_eq!(parse_one("X", untyped("7")).unwrap(), seven);
+        assert_eq!(parse_one("X", typed("7", "u32")).unwrap(), seven);
+
+        let error = parse_one("X", typed("7", "u16")).unwrap_err();
+        assert_eq!(error.key.as_deref(), Some("X"));
+        assert!(
+            error.message.contains("does not match"),
+            "{}",
+            error.message
+        );
+
+        // Names the program does not declare need their type spelled out.
+        let error = parse_one("Y", untyped("7")).unwrap_err();
+        assert_eq!(error.key.as_deref(), Some("Y"));
+        assert!(parse_one("Y", typed("7", "u8")).is_ok());
+    }
+
+    #[test]
+    fn reports_missing_and_unknown_witness_keys() {
+        let template =
+            simplicityhl::TemplateProgram::<ElementsExtension>::new(BOUNDED.to_string()).unwrap();
+        let args = simplicityhl::Arguments::<ElementsExtension>::from(HashMap::from([(
+            WitnessName::from_str_unchecked("LIMIT"),
+            Value::parse_from_str("10", &u32_type()).unwrap(),
+        )]));
+        let compiled = template.instantiate(args, false).unwrap();
+
+        let mut witness = HashMap::from([("X".to_string(), untyped("7"))]);
+        let error = check_witness_keys(&witness, compiled.witness_types()).unwrap_err();
+        assert_eq!(error.kind, DiagnosticKind::WitnessParse);
+        assert_eq!(error.key.as_deref(), Some("FLAG"));
+
+        witness.insert("FLAG".to_string(), untyped("true"));
+        assert!(check_witness_keys(&witness, compiled.witness_types()).is_ok());
+
+        witness.insert("Z".to_string(), untyped("1"));
+        let error = check_witness_keys(&witness, compiled.witness_types()).unwrap_err();
+        assert_eq!(error.key.as_deref(), Some("Z"));
+    }
+
+    #[test]
+    fn tweaks_key_with_program_cmr() {
+        let template =
+            simplicityhl::TemplateProgram::<ElementsExtension>::new(BOUNDED.to_string()).unwrap();
+        let args = simplicityhl::Arguments::<ElementsExtension>::from(HashMap::from([(
+            WitnessName::from_str_unchecked("LIMIT"),
+            Value::parse_from_str("10", &u32_type()).unwrap(),
+        )]));
+        let commit = template.instantiate(args, false).unwrap().commit();
+        let (program_bytes, _) = encode_commit(&commit).unwrap();
+
+        let public_key = format!("02{}", INTERNAL_KEY);
+        let response = tweak(
+            &STANDARD.encode(&program_bytes),
+            Environment::Elements,
+            &public_key,
+        )
+        .unwrap();
+
+        let cmr = commit.cmr().to_byte_array();
+        assert_eq!(response.cmr_hex, hex::encode(cmr));
+
+        let (output_key, parity) = taproot_output_key(
+            &PublicKey::from_str(&public_key).unwrap(),
+            TapNodeHash::from_byte_array(cmr),
+        )
+        .unwrap();
+        assert_eq!(
+            response.tweaked_x_only_public_key_hex,
+            hex::encode(output_key.serialize())
+        );
+        assert_eq!(response.parity, parity.to_u8());
+
+        let prefix = if parity == Parity::Odd { "03" } else { "02" };
+        assert_eq!(
+            response.tweaked_public_key_hex,
+            format!("{}{}", prefix, response.tweaked_x_only_public_key_hex)
+        );
+    }
+
+    /// Compiles `program` and returns the structured error SimplicityHL reports.
+    fn compile_error(program: &str) -> CompileError {
+        let rendered = simplicityhl::TemplateProgram::<ElementsExtension>::new(program.to_string())
+            .err()
+            .expect("program should not compile");
+        CompileError::from_compile(program, rendered)
+    }
+
+    /// The source text a single-line span covers.
+    fn spanned_text(program: &str, span: &SourceSpan) -> String {
+        program
+            .lines()
+            .nth(span.line - 1)
+            .unwrap()
+            .chars()
+            .skip(span.column - 1)
+            .take(span.end_column - span.column)
+            .collect()
+    }
+
+    #[test]
+    fn spans_undefined_variable() {
+        let program = "fn main() {\n    let x: u32 = y;\n}\n";
+        let error = compile_error(program);
+
+        assert!(matches!(error.kind, DiagnosticKind::Type));
+        let span = error.span.unwrap();
+        assert_eq!((span.line, span.end_line), (2, 2));
+        assert_eq!(spanned_text(program, &span), "y");
+        assert!(error.message.contains('y'), "{}", error.message);
+        assert!(!error.message.contains('^'), "{}", error.message);
+    }
+
+    #[test]
+    fn ends_multi_line_span_on_its_last_line() {
+        let program =
+            "fn main() {\n    let x: u32 = jet::add_32(\n        1,\n        2\n    );\n}\n";
+        let error = compile_error(program);
+
+        // The renderer underlines only the first line, from its start.
+        assert_eq!(
+            error.span,
+            Some(SourceSpan {
+                line: 2,
+                column: 1,
+                end_line: 5,
+                end_column: 7,
+            })
+        );
+        assert!(!error.message.is_empty());
+        assert!(!error.message.contains('|'), "{}", error.message);
+    }
+
+    #[test]
+    fn splits_marker_line_from_message() {
+        // The layout of SimplicityHL's own display test: the message follows the marker.
+        let rendered = "  |\n1 | let a1: List<u32, 5> = None;\n  |              ^^^^^^ Expected a power of two\nsecond line";
+        let (span, message) = split_rendered_error(rendered);
+        assert_eq!(
+            span,
+            Some(SourceSpan {
+                line: 1,
+                column: 14,
+                end_line: 1,
+                end_column: 20,
+            })
+        );
+        assert_eq!(message, "Expected a power of two\nsecond line");
+        assert_eq!(
+            split_rendered_error("no span"),
+            (None, "no span".to_string())
+        );
+    }
+}
@@ -1,33 +1,277 @@
+use std::collections::HashMap;
+use std::fmt;
 use anyhow::{Result, anyhow};
 use wasm_bindgen::prelude::*;
 use serde::{Deserialize, Serialize};
-use elements::opcodes::all;
-use elements::{Address, AddressParams, bitcoin, script::Builder};
+use elements::opcodes::{All, all};
+use elements::hashes::{Hash, HashEngine, hmac::Hmac, hmac::HmacEngine, sha256};
+use elements::secp256k1_zkp::{PublicKey, Secp256k1, SecretKey, XOnlyPublicKey};
+use elements::{Address, AddressParams, Script, bitcoin, script::Builder};
+
+use crate::interpreter::decode_num;
+use crate::policy::compile_policy;
+
+/// BIP341 NUMS point, used as the taproot internal key when none is given so
+/// that the script path is the only way to spend.
+const NUMS_INTERNAL_KEY: &str = "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
 
 #[derive(Deserialize)]
 pub struct ConvertRequest {
     pub script: String,
     pub network: String,
+    #[serde(default)]
+    pub address_type: AddressType,
+    /// X-only internal key for `p2tr`; defaults to the NUMS point.
+    pub internal_key: Option<String>,
+    /// Blinding public key for a confidential Elements address.
+    pub blinding_pubkey: Option<String>,
+    /// SLIP-77 master blinding key to derive the blinding public key from.
+    pub master_blinding_key: Option<String>,
+    #[serde(default)]
+    pub syntax: ScriptSyntax,
+    /// Names used for keys in miniscript input, mapped to hex public keys.
+    #[serde(default)]
+    pub keys: HashMap<String, String>,
+}
+
+/// How the `script` of a [`ConvertRequest`] is written.
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScriptSyntax {
+    /// Opcode names and pushes, as accepted by [`parse_script`].
+    #[default]
+    Opcodes,
+    /// A miniscript descriptor, fragment or policy, see [`compile_policy`].
+    Miniscript,
+}
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AddressType {
+    #[default]
+    P2wsh,
+    P2sh,
+    P2shP2wsh,
+    /// Taproot output with the script as its single tapleaf.
+    P2tr,
+}
+
+impl AddressType {
+    /// Script version the script is interpreted under when spent from this address type.
+    pub fn script_context(self) -> ScriptContext {
+        match self {
+            AddressType::P2sh => ScriptContext::Legacy,
+            AddressType::P2wsh | AddressType::P2shP2wsh => ScriptContext::SegwitV0,
+            AddressType::P2tr => ScriptContext::Tapscript,
+        }
+    }
+}
+
+/// Address options for [`parse_human_readable`].
+#[derive(Debug, Default)]
+pub struct AddressOptions {
+    pub address_type: AddressType,
+    pub internal_key: Option<String>,
+    pub blinding_pubkey: Option<String>,
+    pub master_blinding_key: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct ConvertResponse {
     pub hex: String,
     pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unconfidential_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blinding_pubkey: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redeem_script: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub witness_script: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub control_block: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub leaf_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub internal_key: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    pub offset_map: Vec<ScriptElement>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub descriptor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spending_policy: Option<String>,
+    /// Conditions of each way to spend a miniscript, see [`compile_policy`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spending_paths: Option<Vec<Vec<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_satisfaction_weight: Option<u64>,
 }
 
+/// One opcode or push of a script, located by byte offset for the Unchained
+/// script-introspection jets (`jet::get_opcode_from_script`, `jet::get_pubkey_from_script`).
+#[derive(Debug, Serialize)]
+pub struct ScriptElement {
+    pub offset: usize,
+    /// Encoded length, including the opcode and any length prefix.
+    pub length: usize,
+    pub opcode: String,
+    pub kind: ElementKind,
+    /// Pushed bytes in hex, for pushes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// Decoded value, for numbers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<i64>,
+}
+
+/// What an element of a script most likely is. Keys are recognised by their
+/// length and curve validity, so a 32-byte hash can also show up as `x-only-key`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ElementKind {
+    Opcode,
+    Pubkey,
+    XOnlyKey,
+    Number,
+    Data,
+}
+
+/// A parsed script together with the address derived for the requested network.
+#[derive(Debug)]
+pub struct ConvertedScript {
+    pub script: Vec<u8>,
+    pub address: String,
+    /// Set when `address` is confidential.
+    pub unconfidential_address: Option<String>,
+    pub blinding_pubkey: Option<String>,
+    pub spend: SpendData,
+    pub warnings: Vec<String>,
+}
+
+/// Hex-encoded data needed to spend from the derived address, depending on its type.
+#[derive(Debug, Default)]
+pub struct SpendData {
+    /// The script revealed in `scriptSig` for `p2sh` and `p2sh-p2wsh`.
+    pub redeem_script: Option<String>,
+    /// The script revealed in the witness for `p2wsh` and `p2sh-p2wsh`.
+    pub witness_script: Option<String>,
+    pub control_block: Option<String>,
+    pub leaf_hash: Option<String>,
+    pub internal_key: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DisassembleRequest {
+    pub hex: String,
+}
+
+#[derive(Serialize)]
+pub struct DisassembleResponse {
+    pub script: String,
+}
+
+#[derive(Deserialize)]
+pub struct MultisigTemplateRequest {
+    /// Compressed Unchained public key, already tweaked with the program CMR.
+    pub su_key: String,
+    /// Compressed public key of the user.
+    pub user_key: String,
+    /// Compressed key that can spend alone once `recovery_delay` has passed.
+    pub recovery_key: Option<String>,
+    /// Relative timelock in blocks for the recovery path.
+    pub recovery_delay: Option<u16>,
+    /// Any type but `p2tr`: tapscript disables `OP_CHECKMULTISIG`.
+    #[serde(default)]
+    pub address_type: AddressType,
+}
+
+#[derive(Serialize)]
+pub struct MultisigTemplateResponse {
+    pub hex: String,
+    pub asm: String,
+    pub addresses: Vec<NetworkAddress>,
+    pub offsets: TemplateOffsets,
+}
+
+#[derive(Serialize)]
+pub struct NetworkAddress {
+    pub network: String,
+    pub address: String,
+}
+
+/// Byte offsets of the push opcodes in a template script, as taken by
+/// `jet::get_pubkey_from_script` and friends.
+#[derive(Debug, Serialize)]
+pub struct TemplateOffsets {
+    pub su_key: usize,
+    pub user_key: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_key: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_delay: Option<usize>,
+}
+
+/// Networks [`parse_human_readable`] derives addresses for.
+const NETWORKS: &[&str] = &[
+    "elements",
+    "liquid",
+    "liquid_testnet",
+    "bitcoin",
+    "testnet",
+    "testnet4",
+    "signet",
+    "regtest",
+];
+
 #[wasm_bindgen]
 pub fn convert_script(request_json: JsValue) -> Result<JsValue, JsValue> {
     let request: ConvertRequest = serde_wasm_bindgen::from_value(request_json)
         .map_err(|e| JsValue::from_str(&format!("Failed to parse request: {}", e)))?;
 
-    let result = parse_human_readable(&request.script, request.network);
+    let mut options = AddressOptions {
+        address_type: request.address_type,
+        internal_key: request.internal_key,
+        blinding_pubkey: request.blinding_pubkey,
+        master_blinding_key: request.master_blinding_key,
+    };
+
+    // Miniscript is compiled to script bytes first and then goes through the
+    // same parser as hand-written opcodes.
+    let script = match request.syntax {
+        ScriptSyntax::Opcodes => Ok((request.script, None)),
+        ScriptSyntax::Miniscript => {
+            compile_policy(&request.script, &request.keys, options.address_type).and_then(
+                |compiled| {
+                    options.address_type = compiled.address_type;
+                    Ok((disassemble(&compiled.script)?, Some(compiled)))
+                },
+            )
+        }
+    };
+    let result = script.and_then(|(script, policy)| {
+        let converted = parse_human_readable(&script, request.network, &options)?;
+        Ok((script_map(&converted.script)?, converted, policy))
+    });
 
     match result {
-        Ok((script, address)) => {
+        Ok((offset_map, converted, policy)) => {
             let response = ConvertResponse {
-                hex: hex::encode(script),
-                address,
+                hex: hex::encode(converted.script),
+                address: converted.address,
+                unconfidential_address: converted.unconfidential_address,
+                blinding_pubkey: converted.blinding_pubkey,
+                redeem_script: converted.spend.redeem_script,
+                witness_script: converted.spend.witness_script,
+                control_block: converted.spend.control_block,
+                leaf_hash: converted.spend.leaf_hash,
+                internal_key: converted.spend.internal_key,
+                warnings: converted.warnings,
+                offset_map,
+                descriptor: policy.as_ref().map(|p| p.descriptor.clone()),
+                spending_policy: policy.as_ref().map(|p| p.spending_policy.clone()),
+                spending_paths: policy.as_ref().and_then(|p| p.spending_paths.clone()),
+                max_satisfaction_weight: policy.and_then(|p| p.max_satisfaction_weight),
             };
             serde_wasm_bindgen::to_value(&response)
                 .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e)))
@@ -36,191 +280,298 @@ pub fn convert_script(request_json: JsValue) -> Result<JsValue, JsValue> {
     }
 }
 
-pub fn parse_human_readable(input: &str, network: String) -> Result<(Vec<u8>, String)> {
-    let mut builder = Builder::new();
+#[wasm_bindgen]
+pub fn disassemble_script(request_json: JsValue) -> Result<JsValue, JsValue> {
+    let request: DisassembleRequest = serde_wasm_bindgen::from_value(request_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse request: {}", e)))?;
+
+    let result = hex::decode(&request.hex)
+        .map_err(|e| anyhow!("Invalid script hex: {}", e))
+        .and_then(|bytes| disassemble(&bytes));
 
-    let mut expected_len: Option<usize> = None;
-    for token in input.split_whitespace() {
-        if token.starts_with("OP_PUSHBYTES_") {
-            let num_str = token
-                .strip_prefix("OP_PUSHBYTES_")
-                .ok_or(anyhow!("wrong opcode"))?;
+    match result {
+        Ok(script) => serde_wasm_bindgen::to_value(&DisassembleResponse { script })
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e))),
+        Err(e) => Err(JsValue::from_str(&format!("Invalid script: {}", e))),
+    }
+}
 
-            let len: usize = num_str
-                .parse()
-                .map_err(|_| anyhow::anyhow!("invalid length"))?;
-            expected_len = Some(len);
+#[wasm_bindgen]
+pub fn build_multisig_template(request_json: JsValue) -> Result<JsValue, JsValue> {
+    let request: MultisigTemplateRequest = serde_wasm_bindgen::from_value(request_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse request: {}", e)))?;
 
-            continue;
+    match template_response(&request) {
+        Ok(response) => serde_wasm_bindgen::to_value(&response)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e))),
+        Err(e) => Err(JsValue::from_str(&format!("Invalid template: {}", e))),
+    }
+}
+
+/// Builds the template `request` asks for, with its address on every network.
+fn template_response(request: &MultisigTemplateRequest) -> Result<MultisigTemplateResponse> {
+    // A tapscript 2-of-2 would need OP_CHECKSIGADD and x-only keys, which
+    // moves the pushes the Unchained jets read.
+    if request.address_type == AddressType::P2tr {
+        return Err(anyhow!(
+            "p2tr is not supported, since tapscript disables OP_CHECKMULTISIG; \
+             use p2wsh, p2sh or p2sh-p2wsh"
+        ));
+    }
+
+    let recovery = match (request.recovery_key.as_deref(), request.recovery_delay) {
+        (Some(key), Some(delay)) => Some((key, delay)),
+        (None, None) => None,
+        _ => return Err(anyhow!("Recovery key and recovery delay must be given together")),
+    };
+
+    let (asm, offsets) = multisig_template(&request.su_key, &request.user_key, recovery)?;
+    let options = AddressOptions {
+        address_type: request.address_type,
+        ..AddressOptions::default()
+    };
+    let hex = hex::encode(parse_script(&asm)?.as_bytes());
+    let mut addresses = Vec::with_capacity(NETWORKS.len());
+    for network in NETWORKS {
+        let converted = parse_human_readable(&asm, network.to_string(), &options)?;
+        addresses.push(NetworkAddress {
+            network: network.to_string(),
+            address: converted.address,
+        });
+    }
+
+    Ok(MultisigTemplateResponse {
+        hex,
+        asm,
+        addresses,
+        offsets,
+    })
+}
+
+/// Builds the Simplicity Unchained 2-of-2 redeem script between `su_key` and
+/// `user_key`, returning its ASM and the offsets of its pushes.
+///
+/// With a `recovery` key and delay the 2-of-2 stays at the start of the
+/// script, so the key offsets are the same as without one, and is followed by
+/// `OP_IFDUP OP_NOTIF <delay> OP_CSV OP_VERIFY <recovery_key> OP_CHECKSIG OP_ENDIF`.
+/// The recovery path is spent with empty signatures for the 2-of-2.
+pub fn multisig_template(
+    su_key: &str,
+    user_key: &str,
+    recovery: Option<(&str, u16)>,
+) -> Result<(String, TemplateOffsets)> {
+    let su_key = template_key("Unchained", su_key)?;
+    let user_key = template_key("user", user_key)?;
+
+    let mut asm = format!(
+        "OP_PUSHNUM_2 OP_PUSHBYTES_33 {} OP_PUSHBYTES_33 {} OP_PUSHNUM_2 OP_CHECKMULTISIG",
+        su_key, user_key
+    );
+
+    let has_recovery = recovery.is_some();
+    if let Some((key, delay)) = recovery {
+        if delay == 0 {
+            return Err(anyhow!("Recovery delay must be at least one block"));
         }
+        asm.push_str(&format!(
+            " OP_IFDUP OP_NOTIF {} OP_CSV OP_VERIFY OP_PUSHBYTES_33 {} OP_CHECKSIG OP_ENDIF",
+            delay,
+            template_key("recovery", key)?
+        ));
+    }
 
-        if expected_len.is_some() || token.starts_with("0x") {
-            let clean = token.trim_start_matches("0x");
+    // The layout is fixed, so each push is found by its position:
+    // 2 <su_key> <user_key> 2 CHECKMULTISIG [IFDUP NOTIF <delay> CSV VERIFY <recovery_key> ...]
+    let script = parse_script(&asm)?;
+    let instructions = instructions(script.as_bytes())?;
+    let offset_at = |index: usize| instructions[index].offset;
 
-            let bytes =
-                hex::decode(clean).map_err(|_| anyhow::anyhow!("Invalid hex data: {}", token))?;
+    let offsets = TemplateOffsets {
+        su_key: offset_at(1),
+        user_key: offset_at(2),
+        recovery_key: has_recovery.then(|| offset_at(10)),
+        recovery_delay: has_recovery.then(|| offset_at(7)),
+    };
 
-            if let Some(len) = expected_len {
-                if bytes.len() != len {
-                    return Err(anyhow::anyhow!("wrong length"));
-                }
-                expected_len = None;
+    Ok((asm, offsets))
+}
+
+/// Validates a compressed public key for a template and returns it as lowercase hex.
+fn template_key(role: &str, key_hex: &str) -> Result<String> {
+    let key_bytes = hex::decode(key_hex.trim_start_matches("0x"))
+        .map_err(|e| anyhow!("Invalid {} key hex: {}", role, e))?;
+    if key_bytes.len() != 33 {
+        return Err(anyhow!(
+            "The {} key must be a 33-byte compressed public key, got {} bytes",
+            role,
+            key_bytes.len()
+        ));
+    }
+    PublicKey::from_slice(&key_bytes).map_err(|e| anyhow!("Invalid {} key: {}", role, e))?;
+
+    Ok(hex::encode(key_bytes))
+}
+
+/// Lists every opcode and push of `script` with its offset and decoded kind.
+pub fn script_map(script: &[u8]) -> Result<Vec<ScriptElement>> {
+    let mut elements = Vec::new();
+
+    for instruction in instructions(script)? {
+        let opcode = instruction
+            .name()
+            .unwrap_or_else(|| format!("OP_UNKNOWN_0x{:02x}", instruction.opcode));
+
+        let (kind, value) = match (instruction.opcode, instruction.data) {
+            (0x00, _) => (ElementKind::Number, Some(0)),
+            (0x4f | 0x51..=0x60, _) => (ElementKind::Number, Some(instruction.opcode as i64 - 0x50)),
+            (_, Some(data)) if PublicKey::from_slice(data).is_ok() => (ElementKind::Pubkey, None),
+            (_, Some(data)) if data.len() == 32 && XOnlyPublicKey::from_slice(data).is_ok() => {
+                (ElementKind::XOnlyKey, None)
             }
+            // Up to 5 bytes, so that CLTV and CSV arguments are decoded too.
+            (_, Some(data)) => match decode_num(data, 5) {
+                Ok(value) => (ElementKind::Number, Some(value)),
+                Err(_) => (ElementKind::Data, None),
+            },
+            (_, None) => (ElementKind::Opcode, None),
+        };
 
-            builder = builder.push_slice(&bytes);
-            continue;
+        elements.push(ScriptElement {
+            offset: instruction.offset,
+            length: instruction.len,
+            opcode,
+            kind,
+            data: instruction
+                .data
+                .filter(|_| instruction.opcode != 0x00)
+                .map(hex::encode),
+            value,
+        });
+    }
+
+    Ok(elements)
+}
+
+/// Decodes raw script bytes into the token format accepted by [`parse_human_readable`].
+///
+/// Pushes are printed with the opcode that encodes them (`OP_PUSHBYTES_N`,
+/// `OP_PUSHDATA1/2/4`) followed by the data in hex, so non-minimal pushes
+/// survive a round trip.
+pub fn disassemble(script: &[u8]) -> Result<String> {
+    let mut tokens = Vec::new();
+
+    for instruction in instructions(script)? {
+        let name = instruction.name().ok_or_else(|| {
+            anyhow!(
+                "unsupported opcode 0x{:02x} at offset {}",
+                instruction.opcode,
+                instruction.offset
+            )
+        })?;
+        tokens.push(name);
+
+        if let (0x01..=0x4e, Some(data)) = (instruction.opcode, instruction.data) {
+            tokens.push(hex::encode(data));
         }
+    }
 
-        if expected_len.is_some() {
-            return Err(anyhow::anyhow!(
-                "Expected data for PUSHBYTES, found opcode: {}",
-                token
-            ));
+    Ok(tokens.join(" "))
+}
+
+/// One opcode of a raw script, with the data it pushes if it is a push.
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction<'a> {
+    /// Byte offset of the opcode within the script.
+    pub offset: usize,
+    /// Encoded length, including the opcode and any length prefix.
+    pub len: usize,
+    pub opcode: u8,
+    /// Pushed bytes for `OP_0`, `OP_PUSHBYTES_N` and `OP_PUSHDATA1/2/4`.
+    pub data: Option<&'a [u8]>,
+}
+
+impl Instruction<'_> {
+    /// The opcode as the parser spells it, or `None` if it is not in the opcode table.
+    pub fn name(&self) -> Option<String> {
+        match self.opcode {
+            0x01..=0x4b => Some(format!("OP_PUSHBYTES_{}", self.opcode)),
+            0x4c => Some("OP_PUSHDATA1".to_string()),
+            0x4d => Some("OP_PUSHDATA2".to_string()),
+            0x4e => Some("OP_PUSHDATA4".to_string()),
+            code => opcode_name(All::from(code)).map(str::to_string),
         }
+    }
+}
+
+/// Splits raw script bytes into instructions without interpreting them.
+pub fn instructions(script: &[u8]) -> Result<Vec<Instruction<'_>>> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+
+    while offset < script.len() {
+        let start = offset;
+        let code = script[offset];
+        offset += 1;
 
-        let op = match token {
-            "OP_FALSE" | "OP_0" => all::OP_PUSHBYTES_0,
-            "OP_TRUE" | "OP_1" => all::OP_PUSHNUM_1,
-            "OP_PUSHNUM_NEG1" | "OP_1NEGATE" => elements::opcodes::all::OP_PUSHNUM_NEG1,
-            "OP_PUSHNUM_1" => elements::opcodes::all::OP_PUSHNUM_1,
-            "OP_PUSHNUM_2" | "OP_2" => elements::opcodes::all::OP_PUSHNUM_2,
-            "OP_PUSHNUM_3" | "OP_3" => elements::opcodes::all::OP_PUSHNUM_3,
-            "OP_PUSHNUM_4" | "OP_4" => elements::opcodes::all::OP_PUSHNUM_4,
-            "OP_PUSHNUM_5" | "OP_5" => elements::opcodes::all::OP_PUSHNUM_5,
-            "OP_PUSHNUM_6" | "OP_6" => elements::opcodes::all::OP_PUSHNUM_6,
-            "OP_PUSHNUM_7" | "OP_7" => elements::opcodes::all::OP_PUSHNUM_7,
-            "OP_PUSHNUM_8" | "OP_8" => elements::opcodes::all::OP_PUSHNUM_8,
-            "OP_PUSHNUM_9" | "OP_9" => elements::opcodes::all::OP_PUSHNUM_9,
-            "OP_PUSHNUM_10" | "OP_10" => elements::opcodes::all::OP_PUSHNUM_10,
-            "OP_PUSHNUM_11" | "OP_11" => elements::opcodes::all::OP_PUSHNUM_11,
-            "OP_PUSHNUM_12" | "OP_12" => elements::opcodes::all::OP_PUSHNUM_12,
-            "OP_PUSHNUM_13" | "OP_13" => elements::opcodes::all::OP_PUSHNUM_13,
-            "OP_PUSHNUM_14" | "OP_14" => elements::opcodes::all::OP_PUSHNUM_14,
-            "OP_PUSHNUM_15" | "OP_15" => elements::opcodes::all::OP_PUSHNUM_15,
-            "OP_PUSHNUM_16" | "OP_16" => elements::opcodes::all::OP_PUSHNUM_16,
-
-            "OP_NOP" => elements::opcodes::all::OP_NOP,
-            "OP_IF" => elements::opcodes::all::OP_IF,
-            "OP_NOTIF" => elements::opcodes::all::OP_NOTIF,
-            "OP_VERIFY" => elements::opcodes::all::OP_VERIFY,
-            "OP_RETURN" => elements::opcodes::all::OP_RETURN,
-            "OP_ELSE" => elements::opcodes::all::OP_ELSE,
-            "OP_ENDIF" => elements::opcodes::all::OP_ENDIF,
-            "OP_VERIF" => elements::opcodes::all::OP_VERIF,
-            "OP_VERNOTIF" => elements::opcodes::all::OP_VERNOTIF,
-
-            "OP_TOALTSTACK" => elements::opcodes::all::OP_TOALTSTACK,
-            "OP_FROMALTSTACK" => elements::opcodes::all::OP_FROMALTSTACK,
-            "OP_2DROP" => elements::opcodes::all::OP_2DROP,
-            "OP_2DUP" => elements::opcodes::all::OP_2DUP,
-            "OP_3DUP" => elements::opcodes::all::OP_3DUP,
-            "OP_2OVER" => elements::opcodes::all::OP_2OVER,
-            "OP_2ROT" => elements::opcodes::all::OP_2ROT,
-            "OP_2SWAP" => elements::opcodes::all::OP_2SWAP,
-            "OP_IFDUP" => elements::opcodes::all::OP_IFDUP,
-            "OP_DEPTH" => elements::opcodes::all::OP_DEPTH,
-            "OP_DROP" => elements::opcodes::all::OP_DROP,
-            "OP_DUP" => elements::opcodes::all::OP_DUP,
-            "OP_NIP" => elements::opcodes::all::OP_NIP,
-            "OP_OVER" => elements::opcodes::all::OP_OVER,
-            "OP_PICK" => elements::opcodes::all::OP_PICK,
-            "OP_ROLL" => elements::opcodes::all::OP_ROLL,
-            "OP_ROT" => elements::opcodes::all::OP_ROT,
-            "OP_SWAP" => elements::opcodes::all::OP_SWAP,
-            "OP_TUCK" => elements::opcodes::all::OP_TUCK,
-            "OP_SIZE" => elements::opcodes::all::OP_SIZE,
-
-            "OP_INVERT" => elements::opcodes::all::OP_INVERT,
-            "OP_AND" => elements::opcodes::all::OP_AND,
-            "OP_OR" => elements::opcodes::all::OP_OR,
-            "OP_XOR" => elements::opcodes::all::OP_XOR,
-            "OP_EQUAL" => elements::opcodes::all::OP_EQUAL,
-            "OP_EQUALVERIFY" => elements::opcodes::all::OP_EQUALVERIFY,
-            "OP_LSHIFT" => elements::opcodes::all::OP_LSHIFT,
-            "OP_RSHIFT" => elements::opcodes::all::OP_RSHIFT,
-
-            "OP_1ADD" => elements::opcodes::all::OP_1ADD,
-            "OP_1SUB" => elements::opcodes::all::OP_1SUB,
-            "OP_NEGATE" => elements::opcodes::all::OP_NEGATE,
-            "OP_ABS" => elements::opcodes::all::OP_ABS,
-            "OP_NOT" => elements::opcodes::all::OP_NOT,
-            "OP_0NOTEQUAL" => elements::opcodes::all::OP_0NOTEQUAL,
-            "OP_ADD" => elements::opcodes::all::OP_ADD,
-            "OP_SUB" => elements::opcodes::all::OP_SUB,
-            "OP_MUL" => elements::opcodes::all::OP_MUL,
-            "OP_DIV" => elements::opcodes::all::OP_DIV,
-            "OP_MOD" => elements::opcodes::all::OP_MOD,
-            "OP_BOOLAND" => elements::opcodes::all::OP_BOOLAND,
-            "OP_BOOLOR" => elements::opcodes::all::OP_BOOLOR,
-            "OP_NUMEQUAL" => elements::opcodes::all::OP_NUMEQUAL,
-            "OP_NUMEQUALVERIFY" => elements::opcodes::all::OP_NUMEQUALVERIFY,
-            "OP_NUMNOTEQUAL" => elements::opcodes::all::OP_NUMNOTEQUAL,
-            "OP_LESSTHAN" => elements::opcodes::all::OP_LESSTHAN,
-            "OP_GREATERTHAN" => elements::opcodes::all::OP_GREATERTHAN,
-            "OP_LESSTHANOREQUAL" => elements::opcodes::all::OP_LESSTHANOREQUAL,
-            "OP_GREATERTHANOREQUAL" => elements::opcodes::all::OP_GREATERTHANOREQUAL,
-            "OP_MIN" => elements::opcodes::all::OP_MIN,
-            "OP_MAX" => elements::opcodes::all::OP_MAX,
-            "OP_WITHIN" => elements::opcodes::all::OP_WITHIN,
-
-            "OP_RIPEMD160" => elements::opcodes::all::OP_RIPEMD160,
-            "OP_SHA1" => elements::opcodes::all::OP_SHA1,
-            "OP_SHA256" => elements::opcodes::all::OP_SHA256,
-            "OP_HASH160" => elements::opcodes::all::OP_HASH160,
-            "OP_HASH256" => elements::opcodes::all::OP_HASH256,
-            "OP_CODESEPARATOR" => elements::opcodes::all::OP_CODESEPARATOR,
-            "OP_CHECKSIG" => elements::opcodes::all::OP_CHECKSIG,
-            "OP_CHECKSIGVERIFY" => elements::opcodes::all::OP_CHECKSIGVERIFY,
-            "OP_CHECKMULTISIG" => elements::opcodes::all::OP_CHECKMULTISIG,
-            "OP_CHECKMULTISIGVERIFY" => elements::opcodes::all::OP_CHECKMULTISIGVERIFY,
-            "OP_CHECKSIGFROMSTACK" => elements::opcodes::all::OP_CHECKSIGFROMSTACK,
-            "OP_CHECKSIGFROMSTACKVERIFY" => elements::opcodes::all::OP_CHECKSIGFROMSTACKVERIFY,
-
-            "OP_CAT" => elements::opcodes::all::OP_CAT,
-            "OP_SUBSTR" => elements::opcodes::all::OP_SUBSTR,
-            "OP_LEFT" => elements::opcodes::all::OP_LEFT,
-            "OP_RIGHT" => elements::opcodes::all::OP_RIGHT,
-            "OP_CLTV" => elements::opcodes::all::OP_CLTV,
-            "OP_CSV" => elements::opcodes::all::OP_CSV,
-            "OP_ADD64" => elements::opcodes::all::OP_ADD64,
-            "OP_SUB64" => elements::opcodes::all::OP_SUB64,
-            "OP_MUL64" => elements::opcodes::all::OP_MUL64,
-            "OP_DIV64" => elements::opcodes::all::OP_DIV64,
-            "OP_NEG64" => elements::opcodes::all::OP_NEG64,
-            "OP_LESSTHAN64" => elements::opcodes::all::OP_LESSTHAN64,
-            "OP_LESSTHANOREQUAL64" => elements::opcodes::all::OP_LESSTHANOREQUAL64,
-            "OP_GREATERTHAN64" => elements::opcodes::all::OP_GREATERTHAN64,
-            "OP_GREATERTHANOREQUAL64" => elements::opcodes::all::OP_GREATERTHANOREQUAL64,
-            "OP_SCRIPTNUMTOLE64" => elements::opcodes::all::OP_SCRIPTNUMTOLE64,
-            "OP_LE64TOSCRIPTNUM" => elements::opcodes::all::OP_LE64TOSCRIPTNUM,
-            "OP_LE32TOLE64" => elements::opcodes::all::OP_LE32TOLE64,
-            "OP_SHA256INITIALIZE" => elements::opcodes::all::OP_SHA256INITIALIZE,
-            "OP_SHA256UPDATE" => elements::opcodes::all::OP_SHA256UPDATE,
-            "OP_SHA256FINALIZE" => elements::opcodes::all::OP_SHA256FINALIZE,
-            "OP_INSPECTINPUTOUTPOINT" => elements::opcodes::all::OP_INSPECTINPUTOUTPOINT,
-            "OP_INSPECTINPUTASSET" => elements::opcodes::all::OP_INSPECTINPUTASSET,
-            "OP_INSPECTINPUTVALUE" => elements::opcodes::all::OP_INSPECTINPUTVALUE,
-            "OP_INSPECTINPUTSCRIPTPUBKEY" => elements::opcodes::all::OP_INSPECTINPUTSCRIPTPUBKEY,
-            "OP_INSPECTINPUTSEQUENCE" => elements::opcodes::all::OP_INSPECTINPUTSEQUENCE,
-            "OP_INSPECTINPUTISSUANCE" => elements::opcodes::all::OP_INSPECTINPUTISSUANCE,
-            "OP_PUSHCURRENTINPUTINDEX" => elements::opcodes::all::OP_PUSHCURRENTINPUTINDEX,
-            "OP_INSPECTOUTPUTASSET" => elements::opcodes::all::OP_INSPECTOUTPUTASSET,
-            "OP_INSPECTOUTPUTVALUE" => elements::opcodes::all::OP_INSPECTOUTPUTVALUE,
-            "OP_INSPECTOUTPUTNONCE" => elements::opcodes::all::OP_INSPECTOUTPUTNONCE,
-            "OP_INSPECTOUTPUTSCRIPTPUBKEY" => elements::opcodes::all::OP_INSPECTOUTPUTSCRIPTPUBKEY,
-            "OP_INSPECTVERSION" => elements::opcodes::all::OP_INSPECTVERSION,
-            "OP_INSPECTLOCKTIME" => elements::opcodes::all::OP_INSPECTLOCKTIME,
-            "OP_INSPECTNUMINPUTS" => elements::opcodes::all::OP_INSPECTNUMINPUTS,
-            "OP_INSPECTNUMOUTPUTS" => elements::opcodes::all::OP_INSPECTNUMOUTPUTS,
-            "OP_TXWEIGHT" => elements::opcodes::all::OP_TXWEIGHT,
-            "OP_ECMULSCALARVERIFY" => elements::opcodes::all::OP_ECMULSCALARVERIFY,
-            "OP_TWEAKVERIFY" => elements::opcodes::all::OP_TWEAKVERIFY,
-            _ => return Err(anyhow!("unexpected behaviour")),
+        let data = match code {
+            0x00 => Some(&script[offset..offset]),
+            0x01..=0x4b => Some(read_push(script, &mut offset, code as usize)?),
+            0x4c..=0x4e => {
+                let len_bytes = match code {
+                    0x4c => 1,
+                    0x4d => 2,
+                    _ => 4,
+                };
+                let len_field = read_push(script, &mut offset, len_bytes)?;
+                let mut len_le = [0u8; 4];
+                len_le[..len_bytes].copy_from_slice(len_field);
+                let len = u32::from_le_bytes(len_le) as usize;
+
+                Some(read_push(script, &mut offset, len)?)
+            }
+            _ => None,
         };
-        builder = builder.push_opcode(op);
+
+        instructions.push(Instruction {
+            offset: start,
+            len: offset - start,
+            opcode: code,
+            data,
+        });
     }
 
-    let script = builder.into_script();
+    Ok(instructions)
+}
+
+fn read_push<'a>(script: &'a [u8], offset: &mut usize, len: usize) -> Result<&'a [u8]> {
+    let end = offset
+        .checked_add(len)
+        .filter(|end| *end <= script.len())
+        .ok_or_else(|| {
+            anyhow!(
+                "push of {} bytes at offset {} runs past the end of the script",
+                len,
+                offset
+            )
+        })?;
+    let data = &script[*offset..end];
+    *offset = end;
+
+    Ok(data)
+}
+
+pub fn parse_human_readable(
+    input: &str,
+    network: String,
+    options: &AddressOptions,
+) -> Result<ConvertedScript> {
+    let script = parse_script(input)?;
+
+    let context = options.address_type.script_context();
+    let warnings = match NetworkFamily::of(&network) {
+        Some(family) => check_network_opcodes(input, family, context)?,
+        None => Vec::new(),
+    };
 
     // Try Elements networks first
     if let Ok(network_type) = ElementsNetwork::try_from(network.as_str()) {
@@ -230,8 +581,18 @@ pub fn parse_human_readable(input: &str, network: String) -> Result<(Vec<u8>, St
             ElementsNetwork::LiquidTestnet => &AddressParams::LIQUID_TESTNET,
         };
 
-        let address = Address::p2wsh(&script, None, params);
-        return Ok((script.into_bytes(), address.to_string()));
+        let (address, spend) = elements_address(&script, params, options)?;
+        let blinding_pubkey = blinding_pubkey(&address, options)?;
+        let confidential = blinding_pubkey.map(|pk| address.to_confidential(pk));
+
+        return Ok(ConvertedScript {
+            script: script.into_bytes(),
+            address: confidential.as_ref().unwrap_or(&address).to_string(),
+            unconfidential_address: confidential.as_ref().map(|_| address.to_string()),
+            blinding_pubkey: blinding_pubkey.map(|pk| pk.to_string()),
+            spend,
+            warnings,
+        });
     }
 
     // Try Bitcoin networks
@@ -244,9 +605,20 @@ pub fn parse_human_readable(input: &str, network: String) -> Result<(Vec<u8>, St
             BitcoinNetwork::Regtest => bitcoin::Network::Regtest,
         };
 
+        if options.blinding_pubkey.is_some() || options.master_blinding_key.is_some() {
+            return Err(anyhow!("Confidential addresses are only available on Elements networks"));
+        }
+
         let bitcoin_script = bitcoin::ScriptBuf::from_bytes(script.into_bytes());
-        let address = bitcoin::address::Address::p2wsh(&bitcoin_script, network_params);
-        return Ok((bitcoin_script.into_bytes(), address.to_string()));
+        let (address, spend) = bitcoin_address(&bitcoin_script, network_params, options)?;
+        return Ok(ConvertedScript {
+            script: bitcoin_script.into_bytes(),
+            address: address.to_string(),
+            unconfidential_address: None,
+            blinding_pubkey: None,
+            spend,
+            warnings,
+        });
     }
 
     Err(anyhow!(
@@ -255,6 +627,822 @@ pub fn parse_human_readable(input: &str, network: String) -> Result<(Vec<u8>, St
     ))
 }
 
+fn internal_key(options: &AddressOptions) -> Result<XOnlyPublicKey> {
+    let key_hex = options.internal_key.as_deref().unwrap_or(NUMS_INTERNAL_KEY);
+    let key_bytes = hex::decode(key_hex).map_err(|e| anyhow!("Invalid internal key hex: {}", e))?;
+
+    XOnlyPublicKey::from_slice(&key_bytes).map_err(|e| anyhow!("Invalid internal key: {}", e))
+}
+
+/// Picks the blinding public key for `address`: the one given directly, or one
+/// derived SLIP-77 style as `HMAC-SHA256(master_blinding_key, script_pubkey)`.
+fn blinding_pubkey(address: &Address, options: &AddressOptions) -> Result<Option<PublicKey>> {
+    match (&options.blinding_pubkey, &options.master_blinding_key) {
+        (None, None) => Ok(None),
+        (Some(_), Some(_)) => Err(anyhow!(
+            "Pass either a blinding public key or a master blinding key, not both"
+        )),
+        (Some(pubkey_hex), None) => {
+            let pubkey_bytes =
+                hex::decode(pubkey_hex).map_err(|e| anyhow!("Invalid blinding key hex: {}", e))?;
+            let pubkey = PublicKey::from_slice(&pubkey_bytes)
+                .map_err(|e| anyhow!("Invalid blinding public key: {}", e))?;
+            Ok(Some(pubkey))
+        }
+        (None, Some(master_hex)) => {
+            let master_key = hex::decode(master_hex)
+                .map_err(|e| anyhow!("Invalid master blinding key hex: {}", e))?;
+            if master_key.len() != 32 {
+                return Err(anyhow!(
+                    "Master blinding key must be 32 bytes, got {}",
+                    master_key.len()
+                ));
+            }
+
+            let mut engine = HmacEngine::<sha256::Hash>::new(&master_key);
+            engine.input(address.script_pubkey().as_bytes());
+            let blinding_key = Hmac::<sha256::Hash>::from_engine(engine);
+
+            let secret_key = SecretKey::from_slice(blinding_key.as_byte_array())
+                .map_err(|e| anyhow!("Derived blinding key is invalid: {}", e))?;
+            let secp = Secp256k1::signing_only();
+            Ok(Some(PublicKey::from_secret_key(&secp, &secret_key)))
+        }
+    }
+}
+
+fn elements_address(
+    script: &Script,
+    params: &'static AddressParams,
+    options: &AddressOptions,
+) -> Result<(Address, SpendData)> {
+    use elements::taproot::{LeafVersion, TapLeafHash, TaprootBuilder};
+
+    let script_hex = hex::encode(script.as_bytes());
+
+    match options.address_type {
+        AddressType::P2wsh => Ok((
+            Address::p2wsh(script, None, params),
+            SpendData {
+                witness_script: Some(script_hex),
+                ..SpendData::default()
+            },
+        )),
+        AddressType::P2sh => Ok((
+            Address::p2sh(script, None, params),
+            SpendData {
+                redeem_script: Some(script_hex),
+                ..SpendData::default()
+            },
+        )),
+        AddressType::P2shP2wsh => Ok((
+            Address::p2shwsh(script, None, params),
+            SpendData {
+                redeem_script: Some(hex::encode(script.to_v0_p2wsh().as_bytes())),
+                witness_script: Some(script_hex),
+                ..SpendData::default()
+            },
+        )),
+        AddressType::P2tr => {
+            let secp = Secp256k1::verification_only();
+            let internal_key = internal_key(options)?;
+            let leaf_version = LeafVersion::default();
+
+            let spend_info = TaprootBuilder::new()
+                .add_leaf(0, script.clone())
+                .map_err(|e| anyhow!("Failed to build taproot tree: {}", e))?
+                .finalize(&secp, internal_key)
+                .map_err(|_| anyhow!("Failed to finalize taproot tree"))?;
+            let control_block = spend_info
+                .control_block(&(script.clone(), leaf_version))
+                .ok_or_else(|| anyhow!("Script is missing from the taproot tree"))?;
+
+            let address = Address::p2tr(
+                &secp,
+                internal_key,
+                spend_info.merkle_root(),
+                None,
+                params,
+            );
+
+            Ok((
+                address,
+                SpendData {
+                    control_block: Some(hex::encode(control_block.serialize())),
+                    leaf_hash: Some(TapLeafHash::from_script(script, leaf_version).to_string()),
+                    internal_key: Some(internal_key.to_string()),
+                    ..SpendData::default()
+                },
+            ))
+        }
+    }
+}
+
+fn bitcoin_address(
+    script: &bitcoin::Script,
+    network: bitcoin::Network,
+    options: &AddressOptions,
+) -> Result<(bitcoin::Address, SpendData)> {
+    use bitcoin::taproot::{LeafVersion, TapLeafHash, TaprootBuilder};
+
+    let script_hex = hex::encode(script.as_bytes());
+
+    match options.address_type {
+        AddressType::P2wsh => Ok((
+            bitcoin::Address::p2wsh(script, network),
+            SpendData {
+                witness_script: Some(script_hex),
+                ..SpendData::default()
+            },
+        )),
+        AddressType::P2sh => Ok((
+            bitcoin::Address::p2sh(script, network)
+                .map_err(|e| anyhow!("Script cannot be used as a P2SH redeem script: {}", e))?,
+            SpendData {
+                redeem_script: Some(script_hex),
+                ..SpendData::default()
+            },
+        )),
+        AddressType::P2shP2wsh => Ok((
+            bitcoin::Address::p2shwsh(script, network),
+            SpendData {
+                redeem_script: Some(hex::encode(script.to_p2wsh().as_bytes())),
+                witness_script: Some(script_hex),
+                ..SpendData::default()
+            },
+        )),
+        AddressType::P2tr => {
+            let secp = bitcoin::secp256k1::Secp256k1::verification_only();
+            let internal_key = internal_key(options)?;
+
+            let spend_info = TaprootBuilder::new()
+                .add_leaf(0, script.to_owned())
+                .map_err(|e| anyhow!("Failed to build taproot tree: {}", e))?
+                .finalize(&secp, internal_key)
+                .map_err(|_| anyhow!("Failed to finalize taproot tree"))?;
+            let control_block = spend_info
+                .control_block(&(script.to_owned(), LeafVersion::TapScript))
+                .ok_or_else(|| anyhow!("Script is missing from the taproot tree"))?;
+
+            Ok((
+                bitcoin::Address::p2tr_tweaked(spend_info.output_key(), network),
+                SpendData {
+                    control_block: Some(hex::encode(control_block.serialize())),
+                    leaf_hash: Some(
+                        TapLeafHash::from_script(script, LeafVersion::TapScript).to_string(),
+                    ),
+                    internal_key: Some(internal_key.to_string()),
+                    ..SpendData::default()
+                },
+            ))
+        }
+    }
+}
+
+/// Checks every opcode in `input` against what `family` allows in `context`.
+///
+/// Opcodes that can never succeed are rejected outright. On Elements, the
+/// tapscript-only opcodes used outside a tapleaf are reported as warnings
+/// instead, since moving the script into a tapleaf makes them usable.
+pub fn check_network_opcodes(
+    input: &str,
+    family: NetworkFamily,
+    context: ScriptContext,
+) -> Result<Vec<String>> {
+    let mut warnings = Vec::new();
+    let mut data_follows = false;
+
+    for token in tokenize(input) {
+        if data_follows {
+            data_follows = false;
+            continue;
+        }
+        if token.text.starts_with("OP_PUSHBYTES_") || token.text.starts_with("OP_PUSHDATA") {
+            data_follows = true;
+            continue;
+        }
+
+        let Some(op) = opcode_by_name(token.text) else {
+            continue;
+        };
+
+        let Some((problem, fatal)) = opcode_problem(op, family, context) else {
+            continue;
+        };
+
+        let mut error = ScriptError::at(&token, format!("{} {}", token.text, problem));
+        // Tapscript checks multisig with one OP_CHECKSIGADD per key instead.
+        if opcode_availability(op) == OpcodeAvailability::LegacyOnly {
+            error.suggestion = Some("OP_CHECKSIGADD".to_string());
+        }
+        if fatal {
+            return Err(error.into());
+        }
+        warnings.push(error.to_string());
+    }
+
+    Ok(warnings)
+}
+
+/// Describes why `op` is a problem for `family` in `context`, and whether it
+/// makes the script fail outright. `None` means the opcode is fine.
+pub(crate) fn opcode_problem(
+    op: All,
+    family: NetworkFamily,
+    context: ScriptContext,
+) -> Option<(&'static str, bool)> {
+    let tapscript = context == ScriptContext::Tapscript;
+
+    match (opcode_availability(op), family) {
+        (OpcodeAvailability::Everywhere, _) => None,
+        (OpcodeAvailability::Disabled, _) => {
+            Some(("is disabled and fails the script when present", true))
+        }
+        (OpcodeAvailability::LegacyOnly, _) if tapscript => {
+            Some(("is disabled in tapscript", true))
+        }
+        (OpcodeAvailability::LegacyOnly, _) => None,
+        (OpcodeAvailability::TapscriptOnly, _) if tapscript => None,
+        (OpcodeAvailability::TapscriptOnly, _) => Some(("is only enabled in tapscript", false)),
+        (OpcodeAvailability::ElementsTapscript, NetworkFamily::Bitcoin) => {
+            Some(("only exists on Elements and is invalid on Bitcoin", true))
+        }
+        (OpcodeAvailability::Spliced, NetworkFamily::Bitcoin) => {
+            Some(("is disabled on Bitcoin and fails the script when present", true))
+        }
+        (
+            OpcodeAvailability::ElementsTapscript | OpcodeAvailability::Spliced,
+            NetworkFamily::Elements,
+        ) if tapscript => None,
+        (
+            OpcodeAvailability::ElementsTapscript | OpcodeAvailability::Spliced,
+            NetworkFamily::Elements,
+        ) => Some(("is only enabled in Elements tapscript", false)),
+    }
+}
+
+/// Script version the opcodes will be interpreted under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptContext {
+    Legacy,
+    SegwitV0,
+    Tapscript,
+}
+
+/// Chain family a network belongs to, which decides the available opcodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkFamily {
+    Elements,
+    Bitcoin,
+}
+
+impl NetworkFamily {
+    fn of(network: &str) -> Option<Self> {
+        if ElementsNetwork::try_from(network).is_ok() {
+            Some(NetworkFamily::Elements)
+        } else if BitcoinNetwork::try_from(network).is_ok() {
+            Some(NetworkFamily::Bitcoin)
+        } else {
+            None
+        }
+    }
+}
+
+/// Where an opcode may appear without failing the script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpcodeAvailability {
+    /// Valid on both chains.
+    Everywhere,
+    /// `OP_CHECKMULTISIG(VERIFY)`: valid everywhere except tapscript.
+    LegacyOnly,
+    /// `OP_CHECKSIGADD`: valid in tapscript on both chains, unknown before it.
+    TapscriptOnly,
+    /// Disabled on both chains (`OP_MUL`, `OP_VERIF`, ...).
+    Disabled,
+    /// Splice and bitwise opcodes: disabled on Bitcoin, re-enabled in Elements tapscript.
+    Spliced,
+    /// Elements extensions: 64-bit arithmetic, introspection, `OP_CHECKSIGFROMSTACK`, ...
+    ElementsTapscript,
+}
+
+fn opcode_availability(op: All) -> OpcodeAvailability {
+    let code = op.into_u8();
+
+    if [
+        all::OP_2MUL,
+        all::OP_2DIV,
+        all::OP_MUL,
+        all::OP_DIV,
+        all::OP_MOD,
+        all::OP_VERIF,
+        all::OP_VERNOTIF,
+    ]
+    .iter()
+    .any(|disabled| disabled.into_u8() == code)
+    {
+        return OpcodeAvailability::Disabled;
+    }
+
+    if [all::OP_CHECKMULTISIG, all::OP_CHECKMULTISIGVERIFY]
+        .iter()
+        .any(|multisig| multisig.into_u8() == code)
+    {
+        return OpcodeAvailability::LegacyOnly;
+    }
+
+    if code == all::OP_CHECKSIGADD.into_u8() {
+        return OpcodeAvailability::TapscriptOnly;
+    }
+
+    if [
+        all::OP_CAT,
+        all::OP_SUBSTR,
+        all::OP_LEFT,
+        all::OP_RIGHT,
+        all::OP_INVERT,
+        all::OP_AND,
+        all::OP_OR,
+        all::OP_XOR,
+        all::OP_LSHIFT,
+        all::OP_RSHIFT,
+    ]
+    .iter()
+    .any(|spliced| spliced.into_u8() == code)
+    {
+        return OpcodeAvailability::Spliced;
+    }
+
+    // Everything from OP_CHECKSIGFROMSTACK (0xc1) upwards is an Elements extension.
+    if code >= all::OP_CHECKSIGFROMSTACK.into_u8() {
+        return OpcodeAvailability::ElementsTapscript;
+    }
+
+    OpcodeAvailability::Everywhere
+}
+
+/// Opcode names accepted by the parser. The first entry for each opcode is the
+/// canonical name the disassembler prints; later entries are aliases.
+const OPCODES: &[(&str, All)] = &[
+    ("OP_0", all::OP_PUSHBYTES_0),
+    ("OP_FALSE", all::OP_PUSHBYTES_0),
+    ("OP_PUSHNUM_NEG1", all::OP_PUSHNUM_NEG1),
+    ("OP_1NEGATE", all::OP_PUSHNUM_NEG1),
+    ("OP_PUSHNUM_1", all::OP_PUSHNUM_1),
+    ("OP_TRUE", all::OP_PUSHNUM_1),
+    ("OP_1", all::OP_PUSHNUM_1),
+    ("OP_PUSHNUM_2", all::OP_PUSHNUM_2),
+    ("OP_2", all::OP_PUSHNUM_2),
+    ("OP_PUSHNUM_3", all::OP_PUSHNUM_3),
+    ("OP_3", all::OP_PUSHNUM_3),
+    ("OP_PUSHNUM_4", all::OP_PUSHNUM_4),
+    ("OP_4", all::OP_PUSHNUM_4),
+    ("OP_PUSHNUM_5", all::OP_PUSHNUM_5),
+    ("OP_5", all::OP_PUSHNUM_5),
+    ("OP_PUSHNUM_6", all::OP_PUSHNUM_6),
+    ("OP_6", all::OP_PUSHNUM_6),
+    ("OP_PUSHNUM_7", all::OP_PUSHNUM_7),
+    ("OP_7", all::OP_PUSHNUM_7),
+    ("OP_PUSHNUM_8", all::OP_PUSHNUM_8),
+    ("OP_8", all::OP_PUSHNUM_8),
+    ("OP_PUSHNUM_9", all::OP_PUSHNUM_9),
+    ("OP_9", all::OP_PUSHNUM_9),
+    ("OP_PUSHNUM_10", all::OP_PUSHNUM_10),
+    ("OP_10", all::OP_PUSHNUM_10),
+    ("OP_PUSHNUM_11", all::OP_PUSHNUM_11),
+    ("OP_11", all::OP_PUSHNUM_11),
+    ("OP_PUSHNUM_12", all::OP_PUSHNUM_12),
+    ("OP_12", all::OP_PUSHNUM_12),
+    ("OP_PUSHNUM_13", all::OP_PUSHNUM_13),
+    ("OP_13", all::OP_PUSHNUM_13),
+    ("OP_PUSHNUM_14", all::OP_PUSHNUM_14),
+    ("OP_14", all::OP_PUSHNUM_14),
+    ("OP_PUSHNUM_15", all::OP_PUSHNUM_15),
+    ("OP_15", all::OP_PUSHNUM_15),
+    ("OP_PUSHNUM_16", all::OP_PUSHNUM_16),
+    ("OP_16", all::OP_PUSHNUM_16),
+
+    ("OP_NOP", all::OP_NOP),
+    ("OP_IF", all::OP_IF),
+    ("OP_NOTIF", all::OP_NOTIF),
+    ("OP_VERIFY", all::OP_VERIFY),
+    ("OP_RETURN", all::OP_RETURN),
+    ("OP_ELSE", all::OP_ELSE),
+    ("OP_ENDIF", all::OP_ENDIF),
+    ("OP_VERIF", all::OP_VERIF),
+    ("OP_VERNOTIF", all::OP_VERNOTIF),
+
+    ("OP_TOALTSTACK", all::OP_TOALTSTACK),
+    ("OP_FROMALTSTACK", all::OP_FROMALTSTACK),
+    ("OP_2DROP", all::OP_2DROP),
+    ("OP_2DUP", all::OP_2DUP),
+    ("OP_3DUP", all::OP_3DUP),
+    ("OP_2OVER", all::OP_2OVER),
+    ("OP_2ROT", all::OP_2ROT),
+    ("OP_2SWAP", all::OP_2SWAP),
+    ("OP_IFDUP", all::OP_IFDUP),
+    ("OP_DEPTH", all::OP_DEPTH),
+    ("OP_DROP", all::OP_DROP),
+    ("OP_DUP", all::OP_DUP),
+    ("OP_NIP", all::OP_NIP),
+    ("OP_OVER", all::OP_OVER),
+    ("OP_PICK", all::OP_PICK),
+    ("OP_ROLL", all::OP_ROLL),
+    ("OP_ROT", all::OP_ROT),
+    ("OP_SWAP", all::OP_SWAP),
+    ("OP_TUCK", all::OP_TUCK),
+    ("OP_SIZE", all::OP_SIZE),
+
+    ("OP_INVERT", all::OP_INVERT),
+    ("OP_AND", all::OP_AND),
+    ("OP_OR", all::OP_OR),
+    ("OP_XOR", all::OP_XOR),
+    ("OP_EQUAL", all::OP_EQUAL),
+    ("OP_EQUALVERIFY", all::OP_EQUALVERIFY),
+    ("OP_LSHIFT", all::OP_LSHIFT),
+    ("OP_RSHIFT", all::OP_RSHIFT),
+
+    ("OP_1ADD", all::OP_1ADD),
+    ("OP_1SUB", all::OP_1SUB),
+    ("OP_2MUL", all::OP_2MUL),
+    ("OP_2DIV", all::OP_2DIV),
+    ("OP_NEGATE", all::OP_NEGATE),
+    ("OP_ABS", all::OP_ABS),
+    ("OP_NOT", all::OP_NOT),
+    ("OP_0NOTEQUAL", all::OP_0NOTEQUAL),
+    ("OP_ADD", all::OP_ADD),
+    ("OP_SUB", all::OP_SUB),
+    ("OP_MUL", all::OP_MUL),
+    ("OP_DIV", all::OP_DIV),
+    ("OP_MOD", all::OP_MOD),
+    ("OP_BOOLAND", all::OP_BOOLAND),
+    ("OP_BOOLOR", all::OP_BOOLOR),
+    ("OP_NUMEQUAL", all::OP_NUMEQUAL),
+    ("OP_NUMEQUALVERIFY", all::OP_NUMEQUALVERIFY),
+    ("OP_NUMNOTEQUAL", all::OP_NUMNOTEQUAL),
+    ("OP_LESSTHAN", all::OP_LESSTHAN),
+    ("OP_GREATERTHAN", all::OP_GREATERTHAN),
+    ("OP_LESSTHANOREQUAL", all::OP_LESSTHANOREQUAL),
+    ("OP_GREATERTHANOREQUAL", all::OP_GREATERTHANOREQUAL),
+    ("OP_MIN", all::OP_MIN),
+    ("OP_MAX", all::OP_MAX),
+    ("OP_WITHIN", all::OP_WITHIN),
+
+    ("OP_RIPEMD160", all::OP_RIPEMD160),
+    ("OP_SHA1", all::OP_SHA1),
+    ("OP_SHA256", all::OP_SHA256),
+    ("OP_HASH160", all::OP_HASH160),
+    ("OP_HASH256", all::OP_HASH256),
+    ("OP_CODESEPARATOR", all::OP_CODESEPARATOR),
+    ("OP_CHECKSIG", all::OP_CHECKSIG),
+    ("OP_CHECKSIGVERIFY", all::OP_CHECKSIGVERIFY),
+    ("OP_CHECKMULTISIG", all::OP_CHECKMULTISIG),
+    ("OP_CHECKMULTISIGVERIFY", all::OP_CHECKMULTISIGVERIFY),
+    ("OP_CHECKSIGADD", all::OP_CHECKSIGADD),
+    ("OP_CHECKSIGFROMSTACK", all::OP_CHECKSIGFROMSTACK),
+    ("OP_CHECKSIGFROMSTACKVERIFY", all::OP_CHECKSIGFROMSTACKVERIFY),
+
+    ("OP_CAT", all::OP_CAT),
+    ("OP_SUBSTR", all::OP_SUBSTR),
+    ("OP_LEFT", all::OP_LEFT),
+    ("OP_RIGHT", all::OP_RIGHT),
+    ("OP_NOP1", all::OP_NOP1),
+    ("OP_CLTV", all::OP_CLTV),
+    ("OP_CHECKLOCKTIMEVERIFY", all::OP_CLTV),
+    ("OP_NOP2", all::OP_CLTV),
+    ("OP_CSV", all::OP_CSV),
+    ("OP_CHECKSEQUENCEVERIFY", all::OP_CSV),
+    ("OP_NOP3", all::OP_CSV),
+    ("OP_NOP4", all::OP_NOP4),
+    ("OP_NOP5", all::OP_NOP5),
+    ("OP_NOP6", all::OP_NOP6),
+    ("OP_NOP7", all::OP_NOP7),
+    ("OP_NOP8", all::OP_NOP8),
+    ("OP_NOP9", all::OP_NOP9),
+    ("OP_NOP10", all::OP_NOP10),
+    ("OP_ADD64", all::OP_ADD64),
+    ("OP_SUB64", all::OP_SUB64),
+    ("OP_MUL64", all::OP_MUL64),
+    ("OP_DIV64", all::OP_DIV64),
+    ("OP_NEG64", all::OP_NEG64),
+    ("OP_LESSTHAN64", all::OP_LESSTHAN64),
+    ("OP_LESSTHANOREQUAL64", all::OP_LESSTHANOREQUAL64),
+    ("OP_GREATERTHAN64", all::OP_GREATERTHAN64),
+    ("OP_GREATERTHANOREQUAL64", all::OP_GREATERTHANOREQUAL64),
+    ("OP_SCRIPTNUMTOLE64", all::OP_SCRIPTNUMTOLE64),
+    ("OP_LE64TOSCRIPTNUM", all::OP_LE64TOSCRIPTNUM),
+    ("OP_LE32TOLE64", all::OP_LE32TOLE64),
+    ("OP_SHA256INITIALIZE", all::OP_SHA256INITIALIZE),
+    ("OP_SHA256UPDATE", all::OP_SHA256UPDATE),
+    ("OP_SHA256FINALIZE", all::OP_SHA256FINALIZE),
+    ("OP_INSPECTINPUTOUTPOINT", all::OP_INSPECTINPUTOUTPOINT),
+    ("OP_INSPECTINPUTASSET", all::OP_INSPECTINPUTASSET),
+    ("OP_INSPECTINPUTVALUE", all::OP_INSPECTINPUTVALUE),
+    ("OP_INSPECTINPUTSCRIPTPUBKEY", all::OP_INSPECTINPUTSCRIPTPUBKEY),
+    ("OP_INSPECTINPUTSEQUENCE", all::OP_INSPECTINPUTSEQUENCE),
+    ("OP_INSPECTINPUTISSUANCE", all::OP_INSPECTINPUTISSUANCE),
+    ("OP_PUSHCURRENTINPUTINDEX", all::OP_PUSHCURRENTINPUTINDEX),
+    ("OP_INSPECTOUTPUTASSET", all::OP_INSPECTOUTPUTASSET),
+    ("OP_INSPECTOUTPUTVALUE", all::OP_INSPECTOUTPUTVALUE),
+    ("OP_INSPECTOUTPUTNONCE", all::OP_INSPECTOUTPUTNONCE),
+    ("OP_INSPECTOUTPUTSCRIPTPUBKEY", all::OP_INSPECTOUTPUTSCRIPTPUBKEY),
+    ("OP_INSPECTVERSION", all::OP_INSPECTVERSION),
+    ("OP_INSPECTLOCKTIME", all::OP_INSPECTLOCKTIME),
+    ("OP_INSPECTNUMINPUTS", all::OP_INSPECTNUMINPUTS),
+    ("OP_INSPECTNUMOUTPUTS", all::OP_INSPECTNUMOUTPUTS),
+    ("OP_TXWEIGHT", all::OP_TXWEIGHT),
+    ("OP_ECMULSCALARVERIFY", all::OP_ECMULSCALARVERIFY),
+    ("OP_TWEAKVERIFY", all::OP_TWEAKVERIFY),
+];
+
+fn opcode_by_name(name: &str) -> Option<All> {
+    OPCODES
+        .iter()
+        .find(|(candidate, _)| *candidate == name)
+        .map(|(_, op)| *op)
+}
+
+fn opcode_name(op: All) -> Option<&'static str> {
+    OPCODES
+        .iter()
+        .find(|(_, candidate)| candidate.into_u8() == op.into_u8())
+        .map(|(name, _)| *name)
+}
+
+/// Assembles the human-readable token format into a script.
+///
+/// Besides opcode names this accepts data pushes written as `0x<hex>` or
+/// `<hex>`, explicit `OP_PUSHBYTES_N` and `OP_PUSHDATA1/2/4` pushes followed
+/// by their data, and decimal numbers, which are pushed as minimally encoded
+/// script numbers. Hex pushes get the shortest length prefix for their size
+/// but are never turned into `OP_0` or `OP_1`..`OP_16`; write `0`..`16` for
+/// those.
+pub fn parse_script(input: &str) -> Result<Script> {
+    let mut builder = Builder::new();
+
+    let mut pending: Option<(ExplicitPush, Token)> = None;
+    let mut open_conditionals: Vec<Token> = Vec::new();
+
+    for token in tokenize(input) {
+        if let Some((push, push_token)) = pending.take() {
+            if token.text.starts_with("OP_") {
+                return Err(ScriptError::at(
+                    &token,
+                    format!("expected data for {}, found opcode", push_token.text),
+                )
+                .into());
+            }
+
+            let bytes = decode_push_data(token.text).map_err(|e| ScriptError::at(&token, e))?;
+            builder = push
+                .apply(builder, &bytes)
+                .map_err(|e| ScriptError::at(&token, e))?;
+            continue;
+        }
+
+        if let Some(num_str) = token.text.strip_prefix("OP_PUSHBYTES_") {
+            let len: usize = num_str.parse().map_err(|_| {
+                ScriptError::at(&token, "OP_PUSHBYTES_N needs a decimal length")
+            })?;
+            pending = Some((ExplicitPush::Bytes(len), token));
+            continue;
+        }
+
+        let explicit = match token.text {
+            "OP_PUSHDATA1" => Some(ExplicitPush::Data1),
+            "OP_PUSHDATA2" => Some(ExplicitPush::Data2),
+            "OP_PUSHDATA4" => Some(ExplicitPush::Data4),
+            _ => None,
+        };
+        if let Some(push) = explicit {
+            pending = Some((push, token));
+            continue;
+        }
+
+        if token.text.starts_with("0x") || token.text.starts_with('<') {
+            let bytes = decode_push_data(token.text).map_err(|e| ScriptError::at(&token, e))?;
+            builder = builder.push_slice(&bytes);
+            continue;
+        }
+
+        if let Ok(number) = token.text.parse::<i64>() {
+            builder = builder.push_int(number);
+            continue;
+        }
+
+        let op = opcode_by_name(token.text).ok_or_else(|| ScriptError::unknown_opcode(&token))?;
+
+        match token.text {
+            "OP_IF" | "OP_NOTIF" => open_conditionals.push(token),
+            "OP_ELSE" if open_conditionals.is_empty() => {
+                return Err(ScriptError::at(&token, "OP_ELSE without a matching OP_IF").into());
+            }
+            "OP_ENDIF" if open_conditionals.pop().is_none() => {
+                return Err(ScriptError::at(&token, "OP_ENDIF without a matching OP_IF").into());
+            }
+            _ => {}
+        }
+
+        builder = builder.push_opcode(op);
+    }
+
+    if let Some((_, push_token)) = pending {
+        return Err(ScriptError::at(&push_token, "expected push data, found end of script").into());
+    }
+
+    if let Some(unclosed) = open_conditionals.pop() {
+        return Err(ScriptError::at(&unclosed, "conditional is never closed with OP_ENDIF").into());
+    }
+
+    Ok(builder.into_script())
+}
+
+/// A whitespace-separated token of the human-readable script format.
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    index: usize,
+    offset: usize,
+    text: &'a str,
+}
+
+/// Splits `input` on whitespace, keeping each token's index and character offset.
+fn tokenize(input: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start: Option<(usize, usize)> = None;
+
+    for (char_offset, (byte_offset, c)) in input.char_indices().enumerate() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some((char_offset, byte_offset)),
+            (true, Some((offset, byte_start))) => {
+                tokens.push(Token {
+                    index: tokens.len(),
+                    offset,
+                    text: &input[byte_start..byte_offset],
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((offset, byte_start)) = start {
+        tokens.push(Token {
+            index: tokens.len(),
+            offset,
+            text: &input[byte_start..],
+        });
+    }
+
+    tokens
+}
+
+/// Script parse failure pinned to the token that caused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub token_index: usize,
+    pub offset: usize,
+    pub token: String,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl ScriptError {
+    fn at(token: &Token, message: impl fmt::Display) -> Self {
+        ScriptError {
+            token_index: token.index,
+            offset: token.offset,
+            token: token.text.to_string(),
+            message: message.to_string(),
+            suggestion: None,
+        }
+    }
+
+    fn unknown_opcode(token: &Token) -> Self {
+        ScriptError {
+            suggestion: closest_opcode(token.text).map(str::to_string),
+            ..ScriptError::at(token, "unknown opcode")
+        }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "token {} (`{}`) at offset {}: {}",
+            self.token_index, self.token, self.offset, self.message
+        )?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ", did you mean `{}`?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// Finds the known opcode name closest to `name`, if any is reasonably close.
+fn closest_opcode(name: &str) -> Option<&'static str> {
+    let upper = name.to_ascii_uppercase();
+    let max_distance = (upper.len() / 3).max(2);
+
+    OPCODES
+        .iter()
+        .map(|(candidate, _)| *candidate)
+        .chain(["OP_PUSHDATA1", "OP_PUSHDATA2", "OP_PUSHDATA4"])
+        .map(|candidate| (edit_distance(&upper, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two ASCII strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.as_bytes();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.bytes().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Decodes push data written as `<hex>`, `0x<hex>` or bare hex.
+fn decode_push_data(token: &str) -> Result<Vec<u8>> {
+    let clean = match token.strip_prefix('<') {
+        Some(inner) => inner
+            .strip_suffix('>')
+            .ok_or_else(|| anyhow!("unterminated push data"))?,
+        None => token.trim_start_matches("0x"),
+    };
+
+    hex::decode(clean).map_err(|_| anyhow!("invalid hex data"))
+}
+
+/// A push whose encoding is spelled out in the script text.
+#[derive(Debug, Clone, Copy)]
+enum ExplicitPush {
+    Bytes(usize),
+    Data1,
+    Data2,
+    Data4,
+}
+
+impl ExplicitPush {
+    fn name(&self) -> String {
+        match self {
+            ExplicitPush::Bytes(len) => format!("OP_PUSHBYTES_{}", len),
+            ExplicitPush::Data1 => "OP_PUSHDATA1".to_string(),
+            ExplicitPush::Data2 => "OP_PUSHDATA2".to_string(),
+            ExplicitPush::Data4 => "OP_PUSHDATA4".to_string(),
+        }
+    }
+
+    /// Appends `data` using exactly this encoding, even when a shorter one exists.
+    fn apply(self, builder: Builder, data: &[u8]) -> Result<Builder> {
+        let (opcode, len_bytes, max_len) = match self {
+            ExplicitPush::Bytes(len) => {
+                if len > 0x4b {
+                    return Err(anyhow!("OP_PUSHBYTES_N length must be at most 75, got {}", len));
+                }
+                if data.len() != len {
+                    return Err(anyhow!(
+                        "{} expects {} bytes, got {}",
+                        self.name(),
+                        len,
+                        data.len()
+                    ));
+                }
+                return Ok(builder.push_slice(data));
+            }
+            ExplicitPush::Data1 => (all::OP_PUSHDATA1, 1, u8::MAX as usize),
+            ExplicitPush::Data2 => (all::OP_PUSHDATA2, 2, u16::MAX as usize),
+            ExplicitPush::Data4 => (all::OP_PUSHDATA4, 4, u32::MAX as usize),
+        };
+
+        if data.len() > max_len {
+            return Err(anyhow!(
+                "{} bytes do not fit in {}",
+                data.len(),
+                self.name()
+            ));
+        }
+
+        let mut bytes = builder.into_script().into_bytes();
+        bytes.push(opcode.into_u8());
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes()[..len_bytes]);
+        bytes.extend_from_slice(data);
+
+        Ok(Builder::from(bytes))
+    }
+}
+
 #[derive(Debug)]
 enum ElementsNetwork {
     Elements,
@@ -298,3 +1486,459 @@ impl TryFrom<&str> for BitcoinNetwork {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_script_into_instructions() {
+        // OP_0 OP_PUSHBYTES_2 abcd OP_PUSHDATA1 01 ff OP_DUP
+        let script = [0x00, 0x02, 0xab, 0xcd, 0x4c, 0x01, 0xff, 0x76];
+        let instructions = instructions(&script).unwrap();
+
+        let layout: Vec<_> = instructions
+            .iter()
+            .map(|instruction| (instruction.offset, instruction.len, instruction.opcode))
+            .collect();
+        assert_eq!(
+            layout,
+            [(0, 1, 0x00), (1, 3, 0x02), (4, 3, 0x4c), (7, 1, 0x76)]
+        );
+
+        assert_eq!(instructions[0].data, Some(&[][..]));
+        assert_eq!(instructions[1].data, Some(&[0xab, 0xcd][..]));
+        assert_eq!(instructions[2].data, Some(&[0xff][..]));
+        assert_eq!(instructions[3].data, None);
+    }
+
+    #[test]
+    fn rejects_truncated_pushes() {
+        assert!(instructions(&[0x02, 0xab]).is_err());
+        assert!(instructions(&[0x4d, 0x01]).is_err());
+        assert!(instructions(&[0x4c, 0x02, 0xff]).is_err());
+    }
+
+    #[test]
+    fn disassembles_opcodes_and_pushes() {
+        let script = [0x00, 0x02, 0xab, 0xcd, 0x4c, 0x01, 0xff, 0x76, 0x51];
+        assert_eq!(
+            disassemble(&script).unwrap(),
+            "OP_0 OP_PUSHBYTES_2 abcd OP_PUSHDATA1 ff OP_DUP OP_PUSHNUM_1"
+        );
+        assert!(disassemble(&[0x02, 0xab]).is_err());
+    }
+
+    #[test]
+    fn pushes_hex_as_data_and_numbers_minimally() {
+        assert_eq!(parse_script("0x01").unwrap().as_bytes(), [0x01, 0x01]);
+        assert_eq!(parse_script("<>").unwrap().as_bytes(), [0x00]);
+        assert_eq!(parse_script("1").unwrap().as_bytes(), [0x51]);
+        assert_eq!(parse_script("0").unwrap().as_bytes(), [0x00]);
+        assert_eq!(parse_script("-1").unwrap().as_bytes(), [0x4f]);
+        assert_eq!(parse_script("144").unwrap().as_bytes(), [0x02, 0x90, 0x00]);
+    }
+
+    #[test]
+    fn disassembly_round_trips_through_the_parser() {
+        let scripts: &[&[u8]] = &[
+            &[0x76, 0xa9, 0x02, 0xab, 0xcd, 0x88, 0xac],
+            // Non-minimal pushes keep their encoding.
+            &[0x4c, 0x01, 0x05, 0x4d, 0x02, 0x00, 0xab, 0xcd],
+            &[0x00, 0x63, 0x51, 0x67, 0x52, 0x68],
+        ];
+        for script in scripts {
+            let text = disassemble(script).unwrap();
+            assert_eq!(parse_script(&text).unwrap().as_bytes(), *script, "{}", text);
+        }
+    }
+
+    fn push_of(len: usize) -> String {
+        format!("<{}>", "ab".repeat(len))
+    }
+
+    fn script_error(input: &str) -> ScriptError {
+        parse_script(input)
+            .unwrap_err()
+            .downcast::<ScriptError>()
+            .unwrap()
+    }
+
+    #[test]
+    fn picks_push_opcode_at_each_size_boundary() {
+        let cases: &[(usize, &[u8])] = &[
+            (75, &[0x4b]),
+            (76, &[0x4c, 0x4c]),
+            (255, &[0x4c, 0xff]),
+            (256, &[0x4d, 0x00, 0x01]),
+            (65_535, &[0x4d, 0xff, 0xff]),
+            (65_536, &[0x4e, 0x00, 0x00, 0x01, 0x00]),
+        ];
+        for (len, prefix) in cases {
+            let script = parse_script(&push_of(*len)).unwrap();
+            let bytes = script.as_bytes();
+            assert_eq!(&bytes[..prefix.len()], *prefix, "{} bytes", len);
+            assert_eq!(bytes.len(), prefix.len() + len, "{} bytes", len);
+
+            let instructions = instructions(bytes).unwrap();
+            assert_eq!(instructions.len(), 1);
+            assert_eq!(instructions[0].data.map(<[u8]>::len), Some(*len));
+        }
+    }
+
+    #[test]
+    fn rejects_explicit_pushes_past_their_size() {
+        assert!(parse_script(&format!("OP_PUSHBYTES_75 {}", push_of(75))).is_ok());
+        assert!(parse_script(&format!("OP_PUSHBYTES_76 {}", push_of(76))).is_err());
+        assert!(parse_script(&format!("OP_PUSHDATA1 {}", push_of(255))).is_ok());
+        assert!(parse_script(&format!("OP_PUSHDATA1 {}", push_of(256))).is_err());
+        assert!(parse_script(&format!("OP_PUSHDATA2 {}", push_of(65_535))).is_ok());
+        assert!(parse_script(&format!("OP_PUSHDATA2 {}", push_of(65_536))).is_err());
+        assert_eq!(
+            parse_script(&format!("OP_PUSHDATA4 {}", push_of(65_536)))
+                .unwrap()
+                .as_bytes()[..5],
+            [0x4e, 0x00, 0x00, 0x01, 0x00]
+        );
+    }
+
+    #[test]
+    fn points_errors_at_the_token_with_a_suggestion() {
+        let error = script_error("OP_DUP  OP_HASH16 OP_EQUAL");
+        assert_eq!(
+            (error.token_index, error.offset, error.token.as_str()),
+            (1, 8, "OP_HASH16")
+        );
+        assert_eq!(error.suggestion.as_deref(), Some("OP_HASH160"));
+
+        // Offsets count characters, not bytes.
+        let error = script_error("<ab>\u{a0}é OP_1");
+        assert_eq!((error.token_index, error.offset), (1, 5));
+
+        let error = script_error("OP_1 OP_IF OP_1 OP_ENDIF OP_ELSE");
+        assert_eq!(error.token_index, 4);
+        assert!(error.message.contains("without a matching OP_IF"));
+
+        let error = script_error("OP_1 OP_IF OP_1");
+        assert_eq!(error.token_index, 1);
+    }
+
+    fn network_error(input: &str, family: NetworkFamily, context: ScriptContext) -> ScriptError {
+        check_network_opcodes(input, family, context)
+            .unwrap_err()
+            .downcast::<ScriptError>()
+            .unwrap()
+    }
+
+    #[test]
+    fn rejects_elements_opcodes_on_bitcoin() {
+        let script = "OP_PUSHBYTES_1 ac OP_1 OP_1 OP_ADD64";
+        for context in [ScriptContext::SegwitV0, ScriptContext::Tapscript] {
+            let error = network_error(script, NetworkFamily::Bitcoin, context);
+            assert_eq!((error.token_index, error.token.as_str()), (4, "OP_ADD64"));
+            assert!(error.message.contains("only exists on Elements"));
+        }
+
+        // Outside tapscript Elements only warns, since the branch may never run.
+        let warnings =
+            check_network_opcodes(script, NetworkFamily::Elements, ScriptContext::SegwitV0)
+                .unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("only enabled in Elements tapscript"));
+        assert!(
+            check_network_opcodes(script, NetworkFamily::Elements, ScriptContext::Tapscript)
+                .unwrap()
+                .is_empty()
+        );
+
+        let error = parse_human_readable(script, "bitcoin".to_string(), &AddressOptions::default())
+            .unwrap_err();
+        assert!(error.to_string().contains("OP_ADD64"), "{}", error);
+    }
+
+    #[test]
+    fn suggests_checksigadd_for_tapscript_multisig() {
+        let error = network_error(
+            "OP_2 OP_PUSHBYTES_1 ab OP_PUSHBYTES_1 cd OP_2 OP_CHECKMULTISIG",
+            NetworkFamily::Elements,
+            ScriptContext::Tapscript,
+        );
+        assert_eq!(error.token_index, 6);
+        assert_eq!(error.suggestion.as_deref(), Some("OP_CHECKSIGADD"));
+        assert!(
+            error
+                .to_string()
+                .ends_with("did you mean `OP_CHECKSIGADD`?"),
+            "{}",
+            error
+        );
+
+        // OP_CAT is disabled on Bitcoin whatever the script version.
+        assert_eq!(
+            opcode_problem(
+                all::OP_CAT,
+                NetworkFamily::Bitcoin,
+                ScriptContext::Tapscript
+            ),
+            Some((
+                "is disabled on Bitcoin and fails the script when present",
+                true
+            ))
+        );
+    }
+
+    #[test]
+    fn maps_each_element_to_its_offset_and_kind() {
+        let x_only = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let script = parse_script(&format!(
+            "OP_0 OP_PUSHBYTES_33 02{x_only} OP_PUSHBYTES_32 {x_only} 144 \
+             OP_PUSHDATA1 {} OP_1NEGATE OP_16 OP_CHECKSIG",
+            "ab".repeat(20)
+        ))
+        .unwrap();
+        let map = script_map(script.as_bytes()).unwrap();
+
+        let layout: Vec<_> = map
+            .iter()
+            .map(|element| {
+                (
+                    element.offset,
+                    element.length,
+                    element.opcode.as_str(),
+                    element.kind,
+                    element.value,
+                )
+            })
+            .collect();
+        assert_eq!(
+            layout,
+            [
+                (0, 1, "OP_0", ElementKind::Number, Some(0)),
+                (1, 34, "OP_PUSHBYTES_33", ElementKind::Pubkey, None),
+                (35, 33, "OP_PUSHBYTES_32", ElementKind::XOnlyKey, None),
+                (68, 3, "OP_PUSHBYTES_2", ElementKind::Number, Some(144)),
+                (71, 22, "OP_PUSHDATA1", ElementKind::Data, None),
+                (93, 1, "OP_PUSHNUM_NEG1", ElementKind::Number, Some(-1)),
+                (94, 1, "OP_PUSHNUM_16", ElementKind::Number, Some(16)),
+                (95, 1, "OP_CHECKSIG", ElementKind::Opcode, None),
+            ]
+        );
+
+        // Offsets and lengths tile the script, and pushes keep their data.
+        let end = map.last().map(|element| element.offset + element.length);
+        assert_eq!(end, Some(script.len()));
+        assert_eq!(map[0].data, None);
+        assert_eq!(map[2].data.as_deref(), Some(x_only));
+        assert_eq!(map[3].data.as_deref(), Some("9000"));
+    }
+
+    #[test]
+    fn names_tapscript_opcodes_and_upgradable_nops() {
+        assert_eq!(
+            disassemble(&[0xb0, 0xb1, 0xb2, 0xb3, 0xb9, 0xba]).unwrap(),
+            "OP_NOP1 OP_CLTV OP_CSV OP_NOP4 OP_NOP10 OP_CHECKSIGADD"
+        );
+        assert_eq!(
+            parse_script("OP_NOP2 OP_CHECKLOCKTIMEVERIFY OP_NOP3 OP_CHECKSEQUENCEVERIFY")
+                .unwrap()
+                .as_bytes(),
+            [0xb1, 0xb1, 0xb2, 0xb2]
+        );
+
+        // multi_a(2,A,B) as miniscript writes it for tapscript.
+        let key = "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        let multi_a = format!(
+            "OP_PUSHBYTES_32 {key} OP_CHECKSIG OP_PUSHBYTES_32 {key} OP_CHECKSIGADD \
+             OP_PUSHNUM_2 OP_NUMEQUAL"
+        );
+        let script = parse_script(&multi_a).unwrap();
+        assert_eq!(disassemble(script.as_bytes()).unwrap(), multi_a);
+    }
+
+    #[test]
+    fn allows_checksigadd_only_in_tapscript() {
+        for family in [NetworkFamily::Elements, NetworkFamily::Bitcoin] {
+            assert!(
+                check_network_opcodes("OP_CHECKSIGADD", family, ScriptContext::Tapscript)
+                    .unwrap()
+                    .is_empty()
+            );
+            let warnings =
+                check_network_opcodes("OP_CHECKSIGADD", family, ScriptContext::SegwitV0).unwrap();
+            assert_eq!(warnings.len(), 1);
+            assert!(
+                warnings[0].contains("only enabled in tapscript"),
+                "{}",
+                warnings[0]
+            );
+        }
+    }
+
+    /// `<G> OP_CHECKSIG`, whose P2WSH address is the BIP 173 testnet vector.
+    const CHECKSIG_G: &str = "OP_PUSHBYTES_33 \
+        0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798 OP_CHECKSIG";
+
+    fn address_of(network: &str, options: AddressOptions) -> ConvertedScript {
+        parse_human_readable(CHECKSIG_G, network.to_string(), &options).unwrap()
+    }
+
+    fn address_type(address_type: AddressType) -> AddressOptions {
+        AddressOptions {
+            address_type,
+            ..AddressOptions::default()
+        }
+    }
+
+    #[test]
+    fn derives_p2sh_address() {
+        assert_eq!(
+            address_of("testnet", address_type(AddressType::P2sh)).address,
+            "2MvVwHhgE2JyjkjQk72CghrhrJsanKfHfqe"
+        );
+        assert_eq!(
+            address_of("liquid", address_type(AddressType::P2sh)).address,
+            "GkSEheszYzEBMgX9G9ueaAyLVg8gfZwiDY"
+        );
+    }
+
+    #[test]
+    fn derives_p2wsh_address() {
+        assert_eq!(
+            address_of("testnet", address_type(AddressType::P2wsh)).address,
+            "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7"
+        );
+    }
+
+    #[test]
+    fn derives_p2tr_address_with_nums_internal_key() {
+        let converted = address_of("testnet", address_type(AddressType::P2tr));
+        assert_eq!(
+            converted.address,
+            "tb1pekpdz6vr3z8xj7x3q5hgmjxz59s2h5l2t28ldcnr9j8qagtjn64qa84x0t"
+        );
+        assert_eq!(
+            converted.spend.internal_key.as_deref(),
+            Some(NUMS_INTERNAL_KEY)
+        );
+        // Leaf version 0xc0 with the odd output key parity bit.
+        assert!(converted.spend.control_block.unwrap().starts_with("c1"));
+    }
+
+    #[test]
+    fn derives_slip77_blinding_key() {
+        // HMAC-SHA256(master, a914<hash160>87) = 4e5e69d0...abe442f1
+        let converted = address_of(
+            "liquid",
+            AddressOptions {
+                address_type: AddressType::P2sh,
+                master_blinding_key: Some(
+                    "6c2de18eabeff3f7822bc724ad482bef0557f3e1c1e1c75b7a393a5ced4de616".to_string(),
+                ),
+                ..AddressOptions::default()
+            },
+        );
+        assert_eq!(
+            converted.blinding_pubkey.as_deref(),
+            Some("02b3284878085dca4c7bbfb35a3333449be01ea431ea016354ec4d210580ff6dc7")
+        );
+        assert_eq!(
+            converted.address,
+            "VJLAQCXtA5DbDedZGUeG8z47uk24GUe4TgQiXMxQgLTjDNssjBhSEK5ZYDou7bjh1wKmrMKVJ88gwaa8"
+        );
+        assert_eq!(
+            converted.unconfidential_address.as_deref(),
+            Some("GkSEheszYzEBMgX9G9ueaAyLVg8gfZwiDY")
+        );
+    }
+
+    const SU_KEY: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const USER_KEY: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    const RECOVERY_KEY: &str = "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+
+    fn template_request(address_type: AddressType) -> MultisigTemplateRequest {
+        MultisigTemplateRequest {
+            su_key: SU_KEY.to_string(),
+            user_key: USER_KEY.to_string(),
+            recovery_key: Some(RECOVERY_KEY.to_string()),
+            recovery_delay: Some(144),
+            address_type,
+        }
+    }
+
+    #[test]
+    fn template_offsets_point_at_their_pushes() {
+        let response = template_response(&template_request(AddressType::P2wsh)).unwrap();
+        let script = hex::decode(&response.hex).unwrap();
+        let instructions = instructions(&script).unwrap();
+        let push_at = |offset: usize| {
+            let instruction = instructions
+                .iter()
+                .find(|instruction| instruction.offset == offset)
+                .unwrap_or_else(|| panic!("no instruction at offset {}", offset));
+            (instruction.opcode, instruction.data.unwrap())
+        };
+
+        let offsets = &response.offsets;
+        assert_eq!(
+            (
+                offsets.su_key,
+                offsets.user_key,
+                offsets.recovery_delay,
+                offsets.recovery_key
+            ),
+            (1, 35, Some(73), Some(78))
+        );
+        assert_eq!(
+            push_at(offsets.su_key),
+            (0x21, &hex::decode(SU_KEY).unwrap()[..])
+        );
+        assert_eq!(
+            push_at(offsets.user_key),
+            (0x21, &hex::decode(USER_KEY).unwrap()[..])
+        );
+        assert_eq!(
+            push_at(offsets.recovery_key.unwrap()),
+            (0x21, &hex::decode(RECOVERY_KEY).unwrap()[..])
+        );
+        let (_, delay) = push_at(offsets.recovery_delay.unwrap());
+        assert_eq!(decode_num(delay, 5).unwrap(), 144);
+
+        assert_eq!(
+            disassemble(&script).unwrap(),
+            format!(
+                "OP_PUSHNUM_2 OP_PUSHBYTES_33 {} OP_PUSHBYTES_33 {} OP_PUSHNUM_2 \
+                 OP_CHECKMULTISIG OP_IFDUP OP_NOTIF OP_PUSHBYTES_2 9000 OP_CSV OP_VERIFY \
+                 OP_PUSHBYTES_33 {} OP_CHECKSIG OP_ENDIF",
+                SU_KEY, USER_KEY, RECOVERY_KEY
+            )
+        );
+        assert_eq!(response.addresses.len(), NETWORKS.len());
+    }
+
+    #[test]
+    fn template_without_recovery_keeps_key_offsets() {
+        let (_, offsets) = multisig_template(SU_KEY, USER_KEY, None).unwrap();
+        assert_eq!((offsets.su_key, offsets.user_key), (1, 35));
+        assert_eq!((offsets.recovery_key, offsets.recovery_delay), (None, None));
+    }
+
+    #[test]
+    fn rejects_p2tr_template() {
+        let error = template_response(&template_request(AddressType::P2tr))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("p2tr is not supported"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_unpaired_recovery_and_zero_delay() {
+        let mut request = template_request(AddressType::P2wsh);
+        request.recovery_delay = None;
+        assert!(template_response(&request).is_err());
+
+        let error = multisig_template(SU_KEY, USER_KEY, Some((RECOVERY_KEY, 0)))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("at least one block"), "{}", error);
+    }
+}
@@ -5,10 +5,20 @@ use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use wasm_bindgen::prelude::*;
 
+use crate::coin_selection::{Candidate, SelectionTarget, select_coins};
+use crate::fee::{
+    Chain, DUST_LIMIT, FeeEstimate, InputSpend, bitcoin_tx_weight, check_fee_rate, fee_for_weight,
+    input_weight, witness_weight,
+};
+
 #[derive(Debug, Deserialize)]
 pub struct UtxoData {
     pub value: u64,
     pub scriptpubkey: String,
+    /// How the input will be signed, for fee estimation. Defaults to a guess
+    /// from the script type.
+    #[serde(default)]
+    pub spend: Option<InputSpend>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -17,6 +27,12 @@ pub struct CreatePsbtRequest {
     pub outputs: Vec<String>,
     pub utxos: Vec<UtxoData>, // UTXO data for each input (fetched by caller)
     pub network: String,
+    /// Address to send change to. When set, `inputs` and `utxos` are a pool to
+    /// select from, and a change output is added when it is worth creating.
+    pub change_address: Option<String>,
+    /// Fee rate in sat/vB. An output with value `change` then receives
+    /// whatever the fee leaves over.
+    pub fee_rate: Option<f64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -25,6 +41,16 @@ pub struct CreatePsbtResponse {
     pub inputs: usize,
     pub outputs: usize,
     pub network: String,
+    /// The spent inputs, in transaction order.
+    pub selected_inputs: Vec<String>,
+    pub fee: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<u64>,
+    /// Estimated weight once every input is signed.
+    pub weight: u64,
+    pub vsize: u64,
+    /// Fee divided by the estimated vsize, in sat/vB.
+    pub effective_fee_rate: f64,
 }
 
 fn get_network_kind(network: &str) -> Result<Network, String> {
@@ -44,15 +70,27 @@ pub fn create_psbt(request_json: JsValue) -> Result<JsValue, JsValue> {
     let req: CreatePsbtRequest = serde_wasm_bindgen::from_value(request_json)
         .map_err(|e| JsValue::from_str(&format!("Failed to parse request: {}", e)))?;
 
-    let network_type = get_network_kind(&req.network).map_err(|e| JsValue::from_str(&e))?;
+    let response = build_psbt(&req).map_err(|e| JsValue::from_str(&e))?;
+
+    serde_wasm_bindgen::to_value(&response)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e)))
+}
+
+/// Builds the PSBT `req` describes, selecting inputs and adding change as asked.
+fn build_psbt(req: &CreatePsbtRequest) -> Result<CreatePsbtResponse, String> {
+    let network_type = get_network_kind(&req.network)?;
+
+    if let Some(fee_rate) = req.fee_rate {
+        check_fee_rate(fee_rate).map_err(|e| e.to_string())?;
+    }
 
     // Validate inputs and UTXOs match
     if req.inputs.len() != req.utxos.len() {
-        return Err(JsValue::from_str(&format!(
+        return Err(format!(
             "Inputs count ({}) does not match UTXOs count ({})",
             req.inputs.len(),
             req.utxos.len()
-        )));
+        ));
     }
 
     // Parse inputs (txid:vout)
@@ -60,17 +98,17 @@ pub fn create_psbt(request_json: JsValue) -> Result<JsValue, JsValue> {
     for input_str in &req.inputs {
         let parts: Vec<&str> = input_str.split(':').collect();
         if parts.len() != 2 {
-            return Err(JsValue::from_str(&format!(
+            return Err(format!(
                 "Invalid input format. Expected txid:vout, got: {}",
                 input_str
-            )));
+            ));
         }
 
         let txid = bitcoin::Txid::from_str(parts[0])
-            .map_err(|e| JsValue::from_str(&format!("Invalid txid {}: {}", parts[0], e)))?;
+            .map_err(|e| format!("Invalid txid {}: {}", parts[0], e))?;
         let vout: u32 = parts[1]
             .parse()
-            .map_err(|e| JsValue::from_str(&format!("Invalid vout {}: {}", parts[1], e)))?;
+            .map_err(|e| format!("Invalid vout {}: {}", parts[1], e))?;
 
         tx_inputs.push(TxIn {
             previous_output: OutPoint::new(txid, vout),
@@ -80,68 +118,352 @@ pub fn create_psbt(request_json: JsValue) -> Result<JsValue, JsValue> {
         });
     }
 
+    let mut utxo_scripts = Vec::with_capacity(req.utxos.len());
+    let mut witness_weights = Vec::with_capacity(req.utxos.len());
+    for (i, utxo_data) in req.utxos.iter().enumerate() {
+        let script_bytes = hex::decode(&utxo_data.scriptpubkey)
+            .map_err(|e| format!("Invalid scriptpubkey hex for input {}: {}", i, e))?;
+        let weight = witness_weight(Chain::Bitcoin, &script_bytes, utxo_data.spend.as_ref())
+            .map_err(|e| format!("Input {}: {}", i, e))?;
+        utxo_scripts.push(script_bytes);
+        witness_weights.push(weight);
+    }
+
+    // Values come from the caller, so sums are checked rather than left to wrap.
+    let input_total = req
+        .utxos
+        .iter()
+        .try_fold(0u64, |total, utxo_data| total.checked_add(utxo_data.value))
+        .ok_or_else(|| "Input values overflow".to_string())?;
+
     // Parse outputs (address:value)
     let mut tx_outputs = Vec::new();
+    let mut output_total = 0u64;
+    let mut change_index = None;
     for output_str in &req.outputs {
         let parts: Vec<&str> = output_str.split(':').collect();
         if parts.len() != 2 {
-            return Err(JsValue::from_str(&format!(
+            return Err(format!(
                 "Invalid output format. Expected address:value, got: {}",
                 output_str
-            )));
+            ));
         }
 
-        let value: u64 = parts[1]
-            .parse()
-            .map_err(|e| JsValue::from_str(&format!("Invalid value {}: {}", parts[1], e)))?;
+        let value: u64 = match parts[1] {
+            "change" if req.change_address.is_some() || req.fee_rate.is_none() => {
+                return Err(
+                    "A `change` output needs a fee rate and cannot be combined with a change address".to_string(),
+                );
+            }
+            "change" if change_index.is_some() => {
+                return Err("Only one output can receive the change".to_string());
+            }
+            "change" => {
+                change_index = Some(tx_outputs.len());
+                0
+            }
+            value => value
+                .parse()
+                .map_err(|e| format!("Invalid value {}: {}", value, e))?,
+        };
 
         let address = Address::from_str(parts[0])
-            .map_err(|e| JsValue::from_str(&format!("Invalid address {}: {}", parts[0], e)))?
+            .map_err(|e| format!("Invalid address {}: {}", parts[0], e))?
             .require_network(network_type)
             .map_err(|e| {
-                JsValue::from_str(&format!(
+                format!(
                     "Address {} is not valid for network {}: {}",
                     parts[0], req.network, e
-                ))
+                )
             })?;
 
+        output_total = output_total
+            .checked_add(value)
+            .ok_or_else(|| "Output values overflow".to_string())?;
         tx_outputs.push(TxOut {
             value: Amount::from_sat(value),
             script_pubkey: address.script_pubkey(),
         });
     }
 
+    let mut selected: Vec<usize> = (0..tx_inputs.len()).collect();
+    let mut change = None;
+
+    if let Some(change_address) = &req.change_address {
+        let fee_rate = req
+            .fee_rate
+            .ok_or_else(|| "A fee rate is required with a change address".to_string())?;
+        let change_script = Address::from_str(change_address)
+            .map_err(|e| format!("Invalid change address {}: {}", change_address, e))?
+            .require_network(network_type)
+            .map_err(|e| {
+                format!(
+                    "Change address {} is not valid for network {}: {}",
+                    change_address, req.network, e
+                )
+            })?
+            .script_pubkey();
+
+        let candidates: Vec<Candidate> = req
+            .utxos
+            .iter()
+            .zip(&witness_weights)
+            .map(|(utxo_data, witness)| Candidate {
+                value: utxo_data.value,
+                weight: input_weight(*witness),
+            })
+            .collect();
+
+        let base_tx = Transaction {
+            version: bitcoin::transaction::Version::TWO,
+            lock_time: bitcoin::absolute::LockTime::ZERO,
+            input: Vec::new(),
+            output: tx_outputs.clone(),
+        };
+        let change_output = TxOut {
+            value: Amount::ZERO,
+            script_pubkey: change_script.clone(),
+        };
+        let change_spend_weight = witness_weight(Chain::Bitcoin, change_script.as_bytes(), None)
+            .map_err(|e| e.to_string())?;
+
+        let target = SelectionTarget {
+            amount: output_total,
+            pinned_value: 0,
+            base_weight: bitcoin_tx_weight(&base_tx, 0),
+            change_weight: change_output.weight().to_wu(),
+            change_spend_weight: input_weight(change_spend_weight),
+            fee_rate,
+            dust_limit: DUST_LIMIT,
+        };
+        let selection = select_coins(&candidates, &target).map_err(|e| e.to_string())?;
+
+        selected = selection.indices;
+        if let Some(value) = selection.change {
+            tx_outputs.push(TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: change_script,
+            });
+        }
+        change = selection.change;
+    } else {
+        if let Some(fee_rate) = req.fee_rate {
+            let unsigned = Transaction {
+                version: bitcoin::transaction::Version::TWO,
+                lock_time: bitcoin::absolute::LockTime::ZERO,
+                input: tx_inputs.clone(),
+                output: tx_outputs.clone(),
+            };
+            let estimated_fee = fee_for_weight(
+                bitcoin_tx_weight(&unsigned, witness_weights.iter().sum()),
+                fee_rate,
+            );
+
+            let spare = output_total
+                .checked_add(estimated_fee)
+                .and_then(|needed| input_total.checked_sub(needed))
+                .ok_or_else(|| {
+                    format!(
+                        "Inputs ({}) do not cover outputs ({}) plus the estimated fee ({})",
+                        input_total, output_total, estimated_fee
+                    )
+                })?;
+
+            // Leftovers below the dust limit are cheaper to give to the fee than to keep.
+            match change_index {
+                Some(index) if spare >= DUST_LIMIT => {
+                    tx_outputs[index].value = Amount::from_sat(spare);
+                    change = Some(spare);
+                }
+                Some(index) => {
+                    tx_outputs.remove(index);
+                }
+                None if spare < DUST_LIMIT => {}
+                None => {
+                    return Err(format!(
+                        "{} sat would be left over after the fee; add an output with value \
+                         `change` to receive it",
+                        spare
+                    ));
+                }
+            }
+        } else if output_total > input_total {
+            return Err(format!(
+                "Outputs ({}) exceed inputs ({})",
+                output_total, input_total
+            ));
+        }
+    }
+
     let tx = Transaction {
         version: bitcoin::transaction::Version::TWO,
         lock_time: bitcoin::absolute::LockTime::ZERO,
-        input: tx_inputs,
+        input: selected.iter().map(|i| tx_inputs[*i].clone()).collect(),
         output: tx_outputs,
     };
 
-    let mut psbt = Psbt::from_unsigned_tx(tx)
-        .map_err(|e| JsValue::from_str(&format!("Failed to create PSBT: {}", e)))?;
+    let selected_total: u64 = selected.iter().map(|i| req.utxos[*i].value).sum();
+    let spent_total: u64 = tx.output.iter().map(|output| output.value.to_sat()).sum();
+    let estimate = FeeEstimate::new(
+        bitcoin_tx_weight(&tx, selected.iter().map(|i| witness_weights[*i]).sum()),
+        selected_total - spent_total,
+    );
 
-    // Populate witness UTXO for each input from provided UTXO data
-    for (i, utxo_data) in req.utxos.iter().enumerate() {
-        let script_bytes = hex::decode(&utxo_data.scriptpubkey).map_err(|e| {
-            JsValue::from_str(&format!("Invalid scriptpubkey hex for input {}: {}", i, e))
-        })?;
+    let mut psbt =
+        Psbt::from_unsigned_tx(tx).map_err(|e| format!("Failed to create PSBT: {}", e))?;
 
+    // Populate witness UTXO for each input from provided UTXO data
+    for (position, &i) in selected.iter().enumerate() {
         let prev_output = TxOut {
-            value: Amount::from_sat(utxo_data.value),
-            script_pubkey: ScriptBuf::from_bytes(script_bytes),
+            value: Amount::from_sat(req.utxos[i].value),
+            script_pubkey: ScriptBuf::from_bytes(utxo_scripts[i].clone()),
         };
 
-        psbt.inputs[i].witness_utxo = Some(prev_output);
+        psbt.inputs[position].witness_utxo = Some(prev_output);
     }
 
-    let response = CreatePsbtResponse {
+    Ok(CreatePsbtResponse {
         psbt: hex::encode(psbt.serialize()),
         inputs: psbt.inputs.len(),
         outputs: psbt.outputs.len(),
         network: req.network.clone(),
-    };
+        selected_inputs: selected.iter().map(|i| req.inputs[*i].clone()).collect(),
+        fee: estimate.fee,
+        change,
+        weight: estimate.weight,
+        vsize: estimate.vsize,
+        effective_fee_rate: estimate.fee_rate,
+    })
+}
 
-    serde_wasm_bindgen::to_value(&response)
-        .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e)))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elements::bitcoin::hashes::Hash;
+
+    const PAYEE: &str = "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx";
+    const CHANGE: &str = "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7";
+
+    fn utxo(txid_byte: u8, value: u64) -> (String, UtxoData) {
+        let input = format!("{}:0", bitcoin::Txid::from_byte_array([txid_byte; 32]));
+        let data = UtxoData {
+            value,
+            // P2WPKH of the PAYEE key.
+            scriptpubkey: "0014751e76e8199196d454941c45d1b3a323f1433bd6".to_string(),
+            spend: None,
+        };
+        (input, data)
+    }
+
+    fn request(utxos: Vec<(String, UtxoData)>, outputs: &[&str]) -> CreatePsbtRequest {
+        let (inputs, utxos) = utxos.into_iter().unzip();
+        CreatePsbtRequest {
+            inputs,
+            outputs: outputs.iter().map(|output| output.to_string()).collect(),
+            utxos,
+            network: "testnet".to_string(),
+            change_address: None,
+            fee_rate: None,
+        }
+    }
+
+    fn decode(response: &CreatePsbtResponse) -> Transaction {
+        Psbt::deserialize(&hex::decode(&response.psbt).unwrap())
+            .unwrap()
+            .unsigned_tx
+    }
+
+    fn script_of(address: &str) -> ScriptBuf {
+        Address::from_str(address)
+            .unwrap()
+            .assume_checked()
+            .script_pubkey()
+    }
+
+    #[test]
+    fn selects_from_pool_and_adds_change() {
+        let mut req = request(
+            vec![utxo(1, 10_000), utxo(2, 200_000), utxo(3, 5_000)],
+            &[&format!("{}:100000", PAYEE)],
+        );
+        req.change_address = Some(CHANGE.to_string());
+        req.fee_rate = Some(1.0);
+
+        let response = build_psbt(&req).unwrap();
+        assert_eq!(response.selected_inputs, [req.inputs[1].clone()]);
+
+        let change = response.change.unwrap();
+        assert_eq!(100_000 + change + response.fee, 200_000);
+        assert!(response.effective_fee_rate >= 1.0);
+
+        let tx = decode(&response);
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(tx.output[1].value, Amount::from_sat(change));
+        assert_eq!(tx.output[1].script_pubkey, script_of(CHANGE));
+    }
+
+    #[test]
+    fn pool_without_fee_rate_is_rejected() {
+        let mut req = request(vec![utxo(1, 10_000)], &[&format!("{}:5000", PAYEE)]);
+        req.change_address = Some(CHANGE.to_string());
+
+        let error = build_psbt(&req).unwrap_err();
+        assert!(error.contains("fee rate"), "{}", error);
+    }
+
+    #[test]
+    fn fills_change_output_after_the_fee() {
+        let mut req = request(
+            vec![utxo(1, 100_000)],
+            &[&format!("{}:50000", PAYEE), &format!("{}:change", CHANGE)],
+        );
+        req.fee_rate = Some(2.0);
+
+        let response = build_psbt(&req).unwrap();
+        let change = response.change.unwrap();
+        assert_eq!(50_000 + change + response.fee, 100_000);
+        assert!(response.effective_fee_rate >= 2.0);
+
+        let tx = decode(&response);
+        assert_eq!(tx.output.len(), 2);
+        assert_eq!(tx.output[1].value, Amount::from_sat(change));
+    }
+
+    #[test]
+    fn drops_change_output_below_dust() {
+        let mut req = request(
+            vec![utxo(1, 50_500)],
+            &[&format!("{}:50000", PAYEE), &format!("{}:change", CHANGE)],
+        );
+        req.fee_rate = Some(1.0);
+
+        let response = build_psbt(&req).unwrap();
+        assert_eq!(response.change, None);
+        assert_eq!(response.fee, 500);
+
+        let tx = decode(&response);
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].script_pubkey, script_of(PAYEE));
+    }
+
+    #[test]
+    fn rejects_leftover_without_change_output() {
+        let mut req = request(vec![utxo(1, 100_000)], &[&format!("{}:50000", PAYEE)]);
+        req.fee_rate = Some(1.0);
+
+        let error = build_psbt(&req).unwrap_err();
+        assert!(error.contains("would be left over"), "{}", error);
+    }
+
+    #[test]
+    fn change_output_needs_fee_rate() {
+        let req = request(
+            vec![utxo(1, 100_000)],
+            &[&format!("{}:50000", PAYEE), &format!("{}:change", CHANGE)],
+        );
+
+        let error = build_psbt(&req).unwrap_err();
+        assert!(error.contains("needs a fee rate"), "{}", error);
+    }
 }
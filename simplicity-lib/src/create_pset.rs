@@ -1,25 +1,87 @@
 use elements::{
-    Address, AddressParams, AssetId, OutPoint, Transaction, TxIn, TxOut, confidential,
-    encode::serialize, pset::PartiallySignedTransaction,
+    Address, AddressParams, AssetId, AssetIssuance, ContractHash, OutPoint, Transaction, TxIn,
+    TxOut, TxOutSecrets,
+    bitcoin::hashes::{Hash, sha256},
+    confidential::{self, AssetBlindingFactor, ValueBlindingFactor},
+    encode::{deserialize, serialize},
+    pset::PartiallySignedTransaction,
+    secp256k1_zkp::{All, Generator, PublicKey, Secp256k1, SecretKey, ZERO_TWEAK},
 };
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::str::FromStr;
 use wasm_bindgen::prelude::*;
 
+use crate::coin_selection::{Candidate, SelectionTarget, select_coins};
+use crate::fee::{
+    Chain, DUST_LIMIT, FeeEstimate, InputSpend, check_fee_rate, elements_output_weight,
+    elements_tx_weight, fee_for_weight, input_weight, witness_weight,
+};
+
+/// The output an input spends. Explicit outputs can be described by `asset`,
+/// `value` and `scriptpubkey`; confidential ones need `tx_hex` and `blinding_key`.
 #[derive(Debug, Deserialize)]
 pub struct UtxoData {
-    pub asset: String,
-    pub value: u64,
-    pub scriptpubkey: String,
+    #[serde(default)]
+    pub asset: Option<String>,
+    #[serde(default)]
+    pub value: Option<u64>,
+    #[serde(default)]
+    pub scriptpubkey: Option<String>,
+    /// Hex of the transaction that created the output, which carries its
+    /// commitments, nonce and range proof.
+    #[serde(default)]
+    pub tx_hex: Option<String>,
+    /// Hex private blinding key to unblind a confidential output.
+    #[serde(default)]
+    pub blinding_key: Option<String>,
+    /// How the input will be signed, for fee estimation. Defaults to a guess
+    /// from the script type.
+    #[serde(default)]
+    pub spend: Option<InputSpend>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CreatePsetRequest {
     pub inputs: Vec<String>,
+    /// `address:value` or `address:value:asset`. The fee output is always in
+    /// the policy asset, and outputs to confidential addresses are blinded.
     pub outputs: Vec<String>,
     pub utxos: Vec<UtxoData>, // UTXO data for each input (fetched by caller)
+    /// Asset of outputs that do not name one. Defaults to the policy asset.
     pub asset_id: Option<String>,
     pub network: String,
+    /// Address to send change to. When set, `inputs` and `utxos` are a pool to
+    /// select from, and the change and fee outputs are added automatically.
+    /// Inputs carrying `issuances` are always selected.
+    pub change_address: Option<String>,
+    /// Fee rate in sat/vB. The fee output is then added automatically, and an
+    /// output with value `change` receives whatever is left over of its asset.
+    pub fee_rate: Option<f64>,
+    /// Issuances to attach to inputs. With `change_address`, the inputs
+    /// carrying them are spent whatever coin selection picks.
+    #[serde(default)]
+    pub issuances: Vec<IssuanceRequest>,
+}
+
+/// A new issuance, or a reissuance when `reissue_entropy` is set.
+#[derive(Debug, Deserialize)]
+pub struct IssuanceRequest {
+    /// Index into `inputs` of the input carrying the issuance.
+    pub input: usize,
+    /// Amount of the asset to issue, sent to `asset_address`.
+    #[serde(default)]
+    pub amount: u64,
+    pub asset_address: Option<String>,
+    /// Reissuance tokens to create, sent to `token_address`. New issuances only.
+    #[serde(default)]
+    pub token_amount: u64,
+    pub token_address: Option<String>,
+    /// Hex contract hash a new issuance commits to. Defaults to all zeros.
+    pub contract_hash: Option<String>,
+    /// Hex entropy of the asset to reissue, as returned for its issuance. The
+    /// input must spend a confidential reissuance token of that asset.
+    pub reissue_entropy: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -29,6 +91,37 @@ pub struct CreatePsetResponse {
     pub outputs: usize,
     pub network: String,
     pub asset: String,
+    /// The spent inputs, in transaction order.
+    pub selected_inputs: Vec<String>,
+    pub fee: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub change: Vec<AssetAmount>,
+    /// Estimated weight once every input is signed.
+    pub weight: u64,
+    pub vsize: u64,
+    /// Fee divided by the estimated vsize, in sat/vB.
+    pub effective_fee_rate: f64,
+    /// Outputs sent to confidential addresses, whose amounts and assets are hidden.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub blinded_outputs: Vec<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub issuances: Vec<IssuanceResponse>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IssuanceResponse {
+    pub input: usize,
+    pub asset: String,
+    /// Reissuance token of the asset.
+    pub token: String,
+    /// Hex asset entropy, needed to reissue the asset later.
+    pub entropy: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AssetAmount {
+    pub asset: String,
+    pub value: u64,
 }
 
 fn get_network_params(network: &str) -> Result<&'static AddressParams, String> {
@@ -51,28 +144,320 @@ fn get_default_asset(network: &str) -> &'static str {
     }
 }
 
+fn explicit_output(asset: AssetId, script_pubkey: elements::script::Script, value: u64) -> TxOut {
+    TxOut {
+        asset: confidential::Asset::Explicit(asset),
+        value: confidential::Value::Explicit(value),
+        nonce: confidential::Nonce::Null,
+        script_pubkey,
+        witness: elements::TxOutWitness::default(),
+    }
+}
+
+/// An explicit output paying `address`. Outputs to confidential addresses
+/// carry the blinding key in their nonce until they are blinded, as in
+/// Elements Core.
+fn address_output(address: &Address, asset: AssetId, value: u64) -> TxOut {
+    TxOut {
+        nonce: address
+            .blinding_pubkey
+            .map_or(confidential::Nonce::Null, confidential::Nonce::Confidential),
+        ..explicit_output(asset, address.script_pubkey(), value)
+    }
+}
+
+/// Adds `value` to the running total of `asset`. Values come from the
+/// caller, so an overflow is an error rather than a wrap.
+fn add_to_total(
+    totals: &mut BTreeMap<AssetId, u64>,
+    asset: AssetId,
+    value: u64,
+) -> Result<(), String> {
+    let total = totals.entry(asset).or_default();
+    *total = total
+        .checked_add(value)
+        .ok_or_else(|| format!("Values of asset {} overflow", asset))?;
+    Ok(())
+}
+
+/// Recovers the output an input spends and its asset and value secrets,
+/// unblinding it when it is confidential.
+fn resolve_utxo(
+    secp: &Secp256k1<All>,
+    utxo_data: &UtxoData,
+    outpoint: &OutPoint,
+) -> Result<(TxOut, TxOutSecrets), String> {
+    let Some(tx_hex) = &utxo_data.tx_hex else {
+        let asset_str = utxo_data.asset.as_deref().ok_or("Missing asset")?;
+        let asset = AssetId::from_str(asset_str).map_err(|e| format!("Invalid asset ID: {}", e))?;
+        let value = utxo_data.value.ok_or("Missing value")?;
+        let script_hex = utxo_data
+            .scriptpubkey
+            .as_deref()
+            .ok_or("Missing scriptpubkey")?;
+        let script_bytes =
+            hex::decode(script_hex).map_err(|e| format!("Invalid scriptpubkey hex: {}", e))?;
+
+        let prev_output =
+            explicit_output(asset, elements::script::Script::from(script_bytes), value);
+        return Ok((prev_output, explicit_secrets(asset, value)));
+    };
+
+    let tx_bytes = hex::decode(tx_hex).map_err(|e| format!("Invalid transaction hex: {}", e))?;
+    let tx: Transaction =
+        deserialize(&tx_bytes).map_err(|e| format!("Failed to decode transaction: {}", e))?;
+    if tx.txid() != outpoint.txid {
+        return Err(format!(
+            "Transaction {} is not the one spent ({})",
+            tx.txid(),
+            outpoint.txid
+        ));
+    }
+    let prev_output = tx
+        .output
+        .get(outpoint.vout as usize)
+        .cloned()
+        .ok_or_else(|| {
+            format!(
+                "Transaction {} has no output {}",
+                outpoint.txid, outpoint.vout
+            )
+        })?;
+
+    if let (confidential::Asset::Explicit(asset), confidential::Value::Explicit(value)) =
+        (prev_output.asset, prev_output.value)
+    {
+        return Ok((prev_output, explicit_secrets(asset, value)));
+    }
+
+    let key_hex = utxo_data
+        .blinding_key
+        .as_deref()
+        .ok_or("A blinding key is required to spend a confidential output")?;
+    let blinding_key =
+        SecretKey::from_str(key_hex).map_err(|e| format!("Invalid blinding key: {}", e))?;
+    let secrets = match prev_output.asset {
+        confidential::Asset::Explicit(asset) => {
+            unblind_value(secp, &prev_output, asset, blinding_key)?
+        }
+        _ => prev_output
+            .unblind(secp, blinding_key)
+            .map_err(|e| format!("Failed to unblind output: {}", e))?,
+    };
+
+    // The range proof only yields the secrets; make sure they open the commitments.
+    let mismatch = || "Unblinded secrets do not match the output's commitments".to_string();
+    let generator = match prev_output.asset {
+        confidential::Asset::Explicit(asset) if asset == secrets.asset => {
+            Generator::new_unblinded(secp, asset.into_tag())
+        }
+        confidential::Asset::Confidential(generator)
+            if generator
+                == Generator::new_blinded(
+                    secp,
+                    secrets.asset.into_tag(),
+                    secrets.asset_bf.into_inner(),
+                ) =>
+        {
+            generator
+        }
+        _ => return Err(mismatch()),
+    };
+    let value =
+        confidential::Value::new_confidential(secp, secrets.value, generator, secrets.value_bf);
+    if value != prev_output.value {
+        return Err(mismatch());
+    }
+
+    Ok((prev_output, secrets))
+}
+
+/// Rewinds the range proof of an output with an explicit asset and a
+/// confidential value, which `TxOut::unblind` does not accept.
+fn unblind_value(
+    secp: &Secp256k1<All>,
+    output: &TxOut,
+    asset: AssetId,
+    blinding_key: SecretKey,
+) -> Result<TxOutSecrets, String> {
+    let confidential::Value::Confidential(commitment) = output.value else {
+        return Err("Output value is not confidential".to_string());
+    };
+    let shared_secret = output
+        .nonce
+        .shared_secret(&blinding_key)
+        .ok_or("Output has no nonce to unblind with")?;
+    let rangeproof = output
+        .witness
+        .rangeproof
+        .as_ref()
+        .ok_or("Output has no range proof")?;
+
+    let (opening, _) = rangeproof
+        .rewind(
+            secp,
+            commitment,
+            shared_secret,
+            output.script_pubkey.as_bytes(),
+            Generator::new_unblinded(secp, asset.into_tag()),
+        )
+        .map_err(|e| format!("Failed to unblind output: {}", e))?;
+    let value_bf = ValueBlindingFactor::from_slice(&opening.blinding_factor[..])
+        .map_err(|e| format!("Invalid value blinding factor: {}", e))?;
+
+    Ok(TxOutSecrets::new(
+        asset,
+        AssetBlindingFactor::zero(),
+        opening.value,
+        value_bf,
+    ))
+}
+
+fn explicit_secrets(asset: AssetId, value: u64) -> TxOutSecrets {
+    TxOutSecrets::new(
+        asset,
+        AssetBlindingFactor::zero(),
+        value,
+        ValueBlindingFactor::zero(),
+    )
+}
+
+/// An issuance ready to attach to its input.
+struct PreparedIssuance {
+    issuance: AssetIssuance,
+    asset: AssetId,
+    token: AssetId,
+    entropy: sha256::Midstate,
+}
+
+/// Derives the asset and token IDs of an issuance on `input`, spending an
+/// output with `input_secrets`.
+fn prepare_issuance(
+    request: &IssuanceRequest,
+    input: &TxIn,
+    input_secrets: &TxOutSecrets,
+) -> Result<PreparedIssuance, String> {
+    let Some(entropy_hex) = &request.reissue_entropy else {
+        if request.amount == 0 && request.token_amount == 0 {
+            return Err("An issuance needs an amount or a token amount".to_string());
+        }
+
+        let contract_hash = match &request.contract_hash {
+            Some(hash) => ContractHash::from_str(hash)
+                .map_err(|e| format!("Invalid contract hash {}: {}", hash, e))?,
+            None => ContractHash::all_zeros(),
+        };
+        let entropy = AssetId::generate_asset_entropy(input.previous_output, contract_hash);
+
+        // Explicit amounts make this an unblinded issuance, which fixes the token ID.
+        return Ok(PreparedIssuance {
+            issuance: AssetIssuance {
+                asset_blinding_nonce: ZERO_TWEAK,
+                asset_entropy: contract_hash.to_byte_array(),
+                amount: explicit_or_null(request.amount),
+                inflation_keys: explicit_or_null(request.token_amount),
+            },
+            asset: AssetId::from_entropy(entropy),
+            token: AssetId::reissuance_token_from_entropy(entropy, false),
+            entropy,
+        });
+    };
+
+    if request.token_amount != 0 {
+        return Err("A reissuance cannot create more reissuance tokens".to_string());
+    }
+    if request.amount == 0 {
+        return Err("A reissuance needs an amount".to_string());
+    }
+
+    let entropy = parse_entropy(entropy_hex)?;
+    let token = input_secrets.asset;
+    if token != AssetId::reissuance_token_from_entropy(entropy, false)
+        && token != AssetId::reissuance_token_from_entropy(entropy, true)
+    {
+        return Err(format!(
+            "The input holds {}, not a reissuance token for this entropy",
+            token
+        ));
+    }
+    // Consensus reads a zero blinding nonce as a new issuance, so the token
+    // must come from a blinded output.
+    if input_secrets.asset_bf == AssetBlindingFactor::zero() {
+        return Err("Reissuance needs the token input to be confidential".to_string());
+    }
+
+    Ok(PreparedIssuance {
+        issuance: AssetIssuance {
+            asset_blinding_nonce: input_secrets.asset_bf.into_inner(),
+            asset_entropy: entropy.to_byte_array(),
+            amount: confidential::Value::Explicit(request.amount),
+            inflation_keys: confidential::Value::Null,
+        },
+        asset: AssetId::from_entropy(entropy),
+        token,
+        entropy,
+    })
+}
+
+fn explicit_or_null(value: u64) -> confidential::Value {
+    if value == 0 {
+        confidential::Value::Null
+    } else {
+        confidential::Value::Explicit(value)
+    }
+}
+
+/// Parses entropy in the byte order Elements Core displays it in.
+fn parse_entropy(entropy_hex: &str) -> Result<sha256::Midstate, String> {
+    let mut bytes: [u8; 32] = hex::decode(entropy_hex)
+        .map_err(|e| format!("Invalid entropy hex: {}", e))?
+        .try_into()
+        .map_err(|_| "Entropy must be 32 bytes".to_string())?;
+    bytes.reverse();
+    Ok(sha256::Midstate::from_byte_array(bytes))
+}
+
+fn entropy_hex(entropy: sha256::Midstate) -> String {
+    let mut bytes = entropy.to_byte_array();
+    bytes.reverse();
+    hex::encode(bytes)
+}
+
 #[wasm_bindgen]
 pub fn create_pset(request_json: JsValue) -> Result<JsValue, JsValue> {
     let req: CreatePsetRequest = serde_wasm_bindgen::from_value(request_json)
         .map_err(|e| JsValue::from_str(&format!("Failed to parse request: {}", e)))?;
 
-    let params = get_network_params(&req.network).map_err(|e| JsValue::from_str(&e))?;
+    let response = build_pset(&req).map_err(|e| JsValue::from_str(&e))?;
+
+    serde_wasm_bindgen::to_value(&response)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e)))
+}
+
+/// Builds the PSET `req` describes: selects inputs and adds change and fee
+/// outputs as asked, attaches issuances and blinds confidential outputs.
+fn build_pset(req: &CreatePsetRequest) -> Result<CreatePsetResponse, String> {
+    let params = get_network_params(&req.network)?;
 
+    if let Some(fee_rate) = req.fee_rate {
+        check_fee_rate(fee_rate).map_err(|e| e.to_string())?;
+    }
+
+    let policy_asset = AssetId::from_str(get_default_asset(&req.network))
+        .map_err(|e| format!("Invalid default asset ID: {}", e))?;
     let asset = if let Some(asset_str) = &req.asset_id {
-        AssetId::from_str(asset_str)
-            .map_err(|e| JsValue::from_str(&format!("Invalid asset ID: {}", e)))?
+        AssetId::from_str(asset_str).map_err(|e| format!("Invalid asset ID: {}", e))?
     } else {
-        AssetId::from_str(get_default_asset(&req.network))
-            .map_err(|e| JsValue::from_str(&format!("Invalid default asset ID: {}", e)))?
+        policy_asset
     };
 
     // Validate inputs and UTXOs match
     if req.inputs.len() != req.utxos.len() {
-        return Err(JsValue::from_str(&format!(
+        return Err(format!(
             "Inputs count ({}) does not match UTXOs count ({})",
             req.inputs.len(),
             req.utxos.len()
-        )));
+        ));
     }
 
     // Parse inputs (txid:vout)
@@ -80,17 +465,17 @@ pub fn create_pset(request_json: JsValue) -> Result<JsValue, JsValue> {
     for input_str in &req.inputs {
         let parts: Vec<&str> = input_str.split(':').collect();
         if parts.len() != 2 {
-            return Err(JsValue::from_str(&format!(
+            return Err(format!(
                 "Invalid input format. Expected txid:vout, got: {}",
                 input_str
-            )));
+            ));
         }
 
         let txid = elements::Txid::from_str(parts[0])
-            .map_err(|e| JsValue::from_str(&format!("Invalid txid {}: {}", parts[0], e)))?;
+            .map_err(|e| format!("Invalid txid {}: {}", parts[0], e))?;
         let vout: u32 = parts[1]
             .parse()
-            .map_err(|e| JsValue::from_str(&format!("Invalid vout {}: {}", parts[1], e)))?;
+            .map_err(|e| format!("Invalid vout {}: {}", parts[1], e))?;
 
         tx_inputs.push(TxIn {
             previous_output: OutPoint::new(txid, vout),
@@ -102,77 +487,969 @@ pub fn create_pset(request_json: JsValue) -> Result<JsValue, JsValue> {
         });
     }
 
-    // Parse outputs (address:value)
+    let secp = Secp256k1::new();
+    let mut prev_outputs = Vec::with_capacity(req.utxos.len());
+    let mut utxo_secrets = Vec::with_capacity(req.utxos.len());
+    let mut witness_weights = Vec::with_capacity(req.utxos.len());
+    for (i, (utxo_data, tx_input)) in req.utxos.iter().zip(&tx_inputs).enumerate() {
+        let (prev_output, secrets) = resolve_utxo(&secp, utxo_data, &tx_input.previous_output)
+            .map_err(|e| format!("Input {}: {}", i, e))?;
+        let weight = witness_weight(
+            Chain::Elements,
+            prev_output.script_pubkey.as_bytes(),
+            utxo_data.spend.as_ref(),
+        )
+        .map_err(|e| format!("Input {}: {}", i, e))?;
+        prev_outputs.push(prev_output);
+        utxo_secrets.push(secrets);
+        witness_weights.push(weight);
+    }
+
+    // Parse outputs (address:value or address:value:asset)
     let mut tx_outputs = Vec::new();
+    let mut output_totals: BTreeMap<AssetId, u64> = BTreeMap::new();
+    let mut change_indices: BTreeMap<AssetId, usize> = BTreeMap::new();
     for output_str in &req.outputs {
         let parts: Vec<&str> = output_str.split(':').collect();
-        if parts.len() != 2 {
-            return Err(JsValue::from_str(&format!(
-                "Invalid output format. Expected address:value, got: {}",
+        if parts.len() != 2 && parts.len() != 3 {
+            return Err(format!(
+                "Invalid output format. Expected address:value or address:value:asset, got: {}",
                 output_str
-            )));
+            ));
         }
 
-        let value: u64 = parts[1]
-            .parse()
-            .map_err(|e| JsValue::from_str(&format!("Invalid value {}: {}", parts[1], e)))?;
+        let output_asset = match parts.get(2) {
+            Some(asset_str) => AssetId::from_str(asset_str)
+                .map_err(|e| format!("Invalid asset ID {}: {}", asset_str, e))?,
+            None if parts[0] == "fee" => policy_asset,
+            None => asset,
+        };
 
-        let script_pubkey = match parts[0] {
-            "fee" => elements::script::Script::new(),
+        let value: u64 = match parts[1] {
+            "change" if req.change_address.is_some() || req.fee_rate.is_none() => {
+                return Err(
+                    "A `change` output needs a fee rate and cannot be combined with a change address".to_string(),
+                );
+            }
+            "change" if change_indices.contains_key(&output_asset) => {
+                return Err(format!(
+                    "Only one output can receive the change of asset {}",
+                    output_asset
+                ));
+            }
+            "change" => {
+                change_indices.insert(output_asset, tx_outputs.len());
+                0
+            }
+            value => value
+                .parse()
+                .map_err(|e| format!("Invalid value {}: {}", value, e))?,
+        };
+
+        add_to_total(&mut output_totals, output_asset, value)?;
+
+        let output = match parts[0] {
+            "fee" if req.fee_rate.is_some() => {
+                return Err(
+                    "Leave out the fee output when giving a fee rate; it is added automatically"
+                        .to_string(),
+                );
+            }
+            "fee" if output_asset != policy_asset => {
+                return Err(format!(
+                    "The fee must be paid in the policy asset {}",
+                    policy_asset
+                ));
+            }
+            "fee" => explicit_output(output_asset, elements::script::Script::new(), value),
             address_str => {
-                let address = Address::parse_with_params(address_str, params).map_err(|e| {
-                    JsValue::from_str(&format!("Invalid address {}: {}", address_str, e))
-                })?;
-                address.script_pubkey()
+                let address = Address::parse_with_params(address_str, params)
+                    .map_err(|e| format!("Invalid address {}: {}", address_str, e))?;
+                address_output(&address, output_asset, value)
             }
         };
 
-        tx_outputs.push(TxOut {
-            asset: confidential::Asset::Explicit(asset),
-            value: confidential::Value::Explicit(value),
-            nonce: confidential::Nonce::Null,
-            script_pubkey,
-            witness: elements::TxOutWitness::default(),
+        tx_outputs.push(output);
+    }
+
+    // Issued amounts are extra input value of the new asset and its token.
+    let mut issued_totals: BTreeMap<AssetId, u64> = BTreeMap::new();
+    let mut issuances = Vec::new();
+    for request in &req.issuances {
+        let input = tx_inputs.get(request.input).ok_or_else(|| {
+            format!(
+                "Issuance input {} out of bounds ({} inputs)",
+                request.input,
+                tx_inputs.len()
+            )
+        })?;
+        if input.has_issuance() {
+            return Err(format!(
+                "Input {} already carries an issuance",
+                request.input
+            ));
+        }
+
+        let prepared = prepare_issuance(request, input, &utxo_secrets[request.input])
+            .map_err(|e| format!("Issuance on input {}: {}", request.input, e))?;
+        tx_inputs[request.input].asset_issuance = prepared.issuance;
+
+        for (asset_id, amount, address) in [
+            (prepared.asset, request.amount, &request.asset_address),
+            (prepared.token, request.token_amount, &request.token_address),
+        ] {
+            if amount == 0 {
+                continue;
+            }
+            let address_str = address.as_deref().ok_or_else(|| {
+                format!(
+                    "Issuance on input {} needs an address for {}",
+                    request.input, asset_id
+                )
+            })?;
+            let address = Address::parse_with_params(address_str, params)
+                .map_err(|e| format!("Invalid address {}: {}", address_str, e))?;
+
+            tx_outputs.push(address_output(&address, asset_id, amount));
+            add_to_total(&mut issued_totals, asset_id, amount)
+                .and_then(|()| add_to_total(&mut output_totals, asset_id, amount))?;
+        }
+
+        issuances.push(IssuanceResponse {
+            input: request.input,
+            asset: prepared.asset.to_string(),
+            token: prepared.token.to_string(),
+            entropy: entropy_hex(prepared.entropy),
         });
     }
 
-    let tx = Transaction {
+    let mut input_totals = issued_totals.clone();
+    for secrets in &utxo_secrets {
+        add_to_total(&mut input_totals, secrets.asset, secrets.value)?;
+    }
+
+    let mut selected: Vec<usize> = (0..tx_inputs.len()).collect();
+    let mut change = Vec::new();
+
+    if let Some(change_address) = &req.change_address {
+        let fee_rate = req
+            .fee_rate
+            .ok_or_else(|| "A fee rate is required with a change address".to_string())?;
+        let change_to = Address::parse_with_params(change_address, params)
+            .map_err(|e| format!("Invalid change address {}: {}", change_address, e))?;
+
+        // Inputs carrying issuances are spent whatever else is selected; their
+        // value, and the amounts they issue, count towards the outputs.
+        let mut pinned: Vec<usize> = req
+            .issuances
+            .iter()
+            .map(|issuance| issuance.input)
+            .collect();
+        pinned.sort_unstable();
+        pinned.dedup();
+        let pinned_value_of = |wanted: AssetId| {
+            pinned
+                .iter()
+                .filter(|i| utxo_secrets[**i].asset == wanted)
+                .map(|i| utxo_secrets[*i].value)
+                .sum::<u64>()
+                + issued_totals.get(&wanted).copied().unwrap_or(0)
+        };
+
+        // Only UTXOs of an asset can pay for outputs of that asset.
+        let candidates_of = |wanted: AssetId| {
+            let indices: Vec<usize> = (0..req.utxos.len())
+                .filter(|i| utxo_secrets[*i].asset == wanted && !pinned.contains(i))
+                .collect();
+            let candidates: Vec<Candidate> = indices
+                .iter()
+                .map(|i| Candidate {
+                    value: utxo_secrets[*i].value,
+                    weight: input_weight(witness_weights[*i]),
+                })
+                .collect();
+            (indices, candidates)
+        };
+
+        // Other assets are selected first, without a fee of their own; their
+        // inputs and change then add to the weight the policy asset pays for.
+        // Pinned inputs of an asset no output asks for are returned as change.
+        let mut assets: Vec<AssetId> = output_totals
+            .keys()
+            .copied()
+            .chain(pinned.iter().map(|i| utxo_secrets[*i].asset))
+            .filter(|asset| *asset != policy_asset)
+            .collect();
+        assets.sort_unstable();
+        assets.dedup();
+
+        selected = pinned.clone();
+        for output_asset in assets {
+            let (indices, candidates) = candidates_of(output_asset);
+            let target = SelectionTarget {
+                amount: output_totals.get(&output_asset).copied().unwrap_or(0),
+                pinned_value: pinned_value_of(output_asset),
+                base_weight: 0,
+                change_weight: 0,
+                change_spend_weight: 0,
+                fee_rate: 0.0,
+                dust_limit: 1,
+            };
+            let selection = select_coins(&candidates, &target)
+                .map_err(|e| format!("Asset {}: {}", output_asset, e))?;
+
+            selected.extend(selection.indices.iter().map(|i| indices[*i]));
+            if let Some(value) = selection.change {
+                tx_outputs.push(address_output(&change_to, output_asset, value));
+                change.push(AssetAmount {
+                    asset: output_asset.to_string(),
+                    value,
+                });
+            }
+        }
+
+        let mut base_outputs = tx_outputs.clone();
+        base_outputs.push(explicit_output(
+            policy_asset,
+            elements::script::Script::new(),
+            0,
+        ));
+        let base_tx = Transaction {
+            version: 2,
+            lock_time: elements::LockTime::ZERO,
+            input: selected.iter().map(|i| tx_inputs[*i].clone()).collect(),
+            output: base_outputs,
+        };
+        let change_spend_weight =
+            witness_weight(Chain::Elements, change_to.script_pubkey().as_bytes(), None)
+                .map_err(|e| e.to_string())?;
+
+        let (indices, candidates) = candidates_of(policy_asset);
+        let target = SelectionTarget {
+            amount: output_totals.get(&policy_asset).copied().unwrap_or(0),
+            pinned_value: pinned_value_of(policy_asset),
+            base_weight: elements_tx_weight(
+                &base_tx,
+                selected.iter().map(|i| witness_weights[*i]).sum(),
+            ),
+            change_weight: elements_output_weight(&address_output(&change_to, policy_asset, 0)),
+            change_spend_weight: input_weight(change_spend_weight),
+            fee_rate,
+            dust_limit: DUST_LIMIT,
+        };
+        let selection = select_coins(&candidates, &target).map_err(|e| e.to_string())?;
+
+        selected.extend(selection.indices.iter().map(|i| indices[*i]));
+        selected.sort_unstable();
+        if let Some(value) = selection.change {
+            tx_outputs.push(address_output(&change_to, policy_asset, value));
+            change.push(AssetAmount {
+                asset: policy_asset.to_string(),
+                value,
+            });
+        }
+        tx_outputs.push(explicit_output(
+            policy_asset,
+            elements::script::Script::new(),
+            selection.fee,
+        ));
+    } else {
+        let mut fee = None;
+        if let Some(fee_rate) = req.fee_rate {
+            let mut outputs_with_fee = tx_outputs.clone();
+            outputs_with_fee.push(explicit_output(
+                policy_asset,
+                elements::script::Script::new(),
+                0,
+            ));
+            let unsigned = Transaction {
+                version: 2,
+                lock_time: elements::LockTime::ZERO,
+                input: tx_inputs.clone(),
+                output: outputs_with_fee,
+            };
+            fee = Some(fee_for_weight(
+                elements_tx_weight(&unsigned, witness_weights.iter().sum()),
+                fee_rate,
+            ));
+        }
+
+        let mut assets: Vec<AssetId> = input_totals
+            .keys()
+            .chain(output_totals.keys())
+            .copied()
+            .collect();
+        assets.sort_unstable();
+        assets.dedup();
+        if fee.is_some() && !assets.contains(&policy_asset) {
+            assets.push(policy_asset);
+        }
+
+        let mut unused_change = Vec::new();
+        for output_asset in assets {
+            let input_total = input_totals.get(&output_asset).copied().unwrap_or(0);
+            let output_total = output_totals.get(&output_asset).copied().unwrap_or(0);
+            let asset_fee = fee.filter(|_| output_asset == policy_asset).unwrap_or(0);
+
+            let needed = output_total
+                .checked_add(asset_fee)
+                .ok_or_else(|| format!("Outputs of asset {} overflow", output_asset))?;
+            let Some(spare) = input_total.checked_sub(needed) else {
+                return Err(format!(
+                    "Inputs ({}) do not cover outputs including the fee ({}) for asset {}",
+                    input_total, needed, output_asset
+                ));
+            };
+
+            // Without a fee rate the caller sets the fee output by hand. Unlike
+            // Bitcoin, a surplus is not an implicit fee on Elements.
+            if fee.is_none() {
+                if spare != 0 {
+                    return Err(format!(
+                        "Inputs ({}) and outputs including the fee ({}) do not balance for asset {}",
+                        input_total, needed, output_asset
+                    ));
+                }
+                continue;
+            }
+
+            // Policy asset leftovers below the dust limit are cheaper to give
+            // to the fee than to keep; other assets cannot go to the fee.
+            let dust_limit = if output_asset == policy_asset {
+                DUST_LIMIT
+            } else {
+                1
+            };
+            match change_indices.get(&output_asset) {
+                Some(&index) if spare >= dust_limit => {
+                    tx_outputs[index].value = confidential::Value::Explicit(spare);
+                    change.push(AssetAmount {
+                        asset: output_asset.to_string(),
+                        value: spare,
+                    });
+                }
+                Some(&index) => {
+                    unused_change.push(index);
+                    fee = fee.map(|fee| fee + spare);
+                }
+                None if spare < dust_limit => fee = fee.map(|fee| fee + spare),
+                None => {
+                    return Err(format!(
+                        "{} of asset {} would be left over; add an output with value \
+                         `change` to receive it",
+                        spare, output_asset
+                    ));
+                }
+            }
+        }
+
+        tx_outputs = tx_outputs
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !unused_change.contains(i))
+            .map(|(_, output)| output)
+            .collect();
+        if let Some(fee) = fee {
+            tx_outputs.push(explicit_output(
+                policy_asset,
+                elements::script::Script::new(),
+                fee,
+            ));
+        }
+    }
+
+    let mut tx = Transaction {
         version: 2,
         lock_time: elements::LockTime::ZERO,
-        input: tx_inputs,
+        input: selected.iter().map(|i| tx_inputs[*i].clone()).collect(),
         output: tx_outputs,
     };
 
+    let fee: u64 = tx
+        .output
+        .iter()
+        .filter(|output| output.is_fee())
+        .filter_map(|output| output.value.explicit())
+        .sum();
+    let estimate = FeeEstimate::new(
+        elements_tx_weight(&tx, selected.iter().map(|i| witness_weights[*i]).sum()),
+        fee,
+    );
+
+    // PSET outputs carry the blinding key in their own field instead.
+    let blinding_keys: Vec<Option<PublicKey>> = tx
+        .output
+        .iter_mut()
+        .map(
+            |output| match std::mem::replace(&mut output.nonce, confidential::Nonce::Null) {
+                confidential::Nonce::Confidential(key) => Some(key),
+                _ => None,
+            },
+        )
+        .collect();
+
     let mut pset = PartiallySignedTransaction::from_tx(tx);
 
     // Populate witness UTXO for each input from provided UTXO data
-    for (i, utxo_data) in req.utxos.iter().enumerate() {
-        let asset_id = AssetId::from_str(&utxo_data.asset)
-            .map_err(|e| JsValue::from_str(&format!("Invalid asset ID for input {}: {}", i, e)))?;
+    for (position, &i) in selected.iter().enumerate() {
+        pset.inputs_mut()[position].witness_utxo = Some(prev_outputs[i].clone());
+    }
 
-        let script_bytes = hex::decode(&utxo_data.scriptpubkey).map_err(|e| {
-            JsValue::from_str(&format!("Invalid scriptpubkey hex for input {}: {}", i, e))
-        })?;
+    let mut blinded_outputs = Vec::new();
+    for (index, key) in blinding_keys.into_iter().enumerate() {
+        if let Some(key) = key {
+            let output = &mut pset.outputs_mut()[index];
+            output.blinding_key = Some(elements::bitcoin::PublicKey::new(key));
+            output.blinder_index = Some(0);
+            blinded_outputs.push(index);
+        }
+    }
 
-        let prev_output = TxOut {
-            asset: confidential::Asset::Explicit(asset_id),
-            value: confidential::Value::Explicit(utxo_data.value),
-            nonce: confidential::Nonce::Null,
-            script_pubkey: elements::script::Script::from(script_bytes),
-            witness: elements::TxOutWitness::default(),
-        };
+    let spends_confidential = selected
+        .iter()
+        .any(|i| prev_outputs[*i].value.is_confidential());
+    if spends_confidential && blinded_outputs.is_empty() {
+        return Err(
+            "Spending confidential inputs needs at least one output to a confidential address \
+             to balance their blinding factors"
+                .to_string(),
+        );
+    }
 
-        pset.inputs_mut()[i].witness_utxo = Some(prev_output);
+    if !blinded_outputs.is_empty() {
+        // The last output's blinding factors are chosen so that the
+        // commitments balance against the inputs' secrets.
+        let input_secrets: HashMap<usize, TxOutSecrets> = selected
+            .iter()
+            .enumerate()
+            .map(|(position, &i)| (position, utxo_secrets[i]))
+            .collect();
+
+        pset.blind_last(&mut rand::thread_rng(), &secp, &input_secrets)
+            .map_err(|e| format!("Failed to blind outputs: {}", e))?;
     }
 
-    let response = CreatePsetResponse {
+    Ok(CreatePsetResponse {
         pset: hex::encode(serialize(&pset)),
         inputs: pset.inputs().len(),
         outputs: pset.outputs().len(),
         network: req.network.clone(),
         asset: asset.to_string(),
-    };
+        selected_inputs: selected.iter().map(|i| req.inputs[*i].clone()).collect(),
+        fee,
+        change,
+        weight: estimate.weight,
+        vsize: estimate.vsize,
+        effective_fee_rate: estimate.fee_rate,
+        blinded_outputs,
+        issuances,
+    })
+}
 
-    serde_wasm_bindgen::to_value(&response)
-        .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e)))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elements::secp256k1_zkp::RangeProof;
+    use rand::thread_rng;
+
+    const VALUE: u64 = 150_000;
+
+    fn asset() -> AssetId {
+        AssetId::from_str(get_default_asset("liquid")).unwrap()
+    }
+
+    /// A transaction whose only output is `output`, and the outpoint spending it.
+    fn funding(output: TxOut) -> (String, OutPoint) {
+        let tx = Transaction {
+            version: 2,
+            lock_time: elements::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![output],
+        };
+        (hex::encode(serialize(&tx)), OutPoint::new(tx.txid(), 0))
+    }
+
+    fn utxo_data(tx_hex: String, blinding_key: &SecretKey) -> UtxoData {
+        UtxoData {
+            asset: None,
+            value: None,
+            scriptpubkey: None,
+            tx_hex: Some(tx_hex),
+            blinding_key: Some(blinding_key.display_secret().to_string()),
+            spend: None,
+        }
+    }
+
+    /// A blinding key pair and a confidential address for it.
+    fn confidential_address(secp: &Secp256k1<All>) -> (SecretKey, Address) {
+        let blinding_key = SecretKey::new(&mut thread_rng());
+        let spending_key = SecretKey::new(&mut thread_rng());
+        let address = Address::p2wpkh(
+            &elements::bitcoin::PublicKey::new(spending_key.public_key(secp)),
+            Some(blinding_key.public_key(secp)),
+            &AddressParams::LIQUID,
+        );
+        (blinding_key, address)
+    }
+
+    #[test]
+    fn unblinds_fully_confidential_output() {
+        let secp = Secp256k1::new();
+        let (blinding_key, address) = confidential_address(&secp);
+        let (output, asset_bf, value_bf, _) = TxOut::new_not_last_confidential(
+            &mut thread_rng(),
+            &secp,
+            VALUE,
+            address,
+            asset(),
+            &[explicit_secrets(asset(), VALUE)],
+        )
+        .unwrap();
+
+        let (tx_hex, outpoint) = funding(output);
+        let (_, secrets) =
+            resolve_utxo(&secp, &utxo_data(tx_hex, &blinding_key), &outpoint).unwrap();
+
+        assert_eq!(secrets.asset, asset());
+        assert_eq!(secrets.value, VALUE);
+        assert_eq!(secrets.asset_bf, asset_bf);
+        assert_eq!(secrets.value_bf, value_bf);
+    }
+
+    #[test]
+    fn unblinds_output_with_explicit_asset() {
+        let secp = Secp256k1::new();
+        let (blinding_key, address) = confidential_address(&secp);
+        let (nonce, shared_secret) = confidential::Nonce::new_confidential(
+            &mut thread_rng(),
+            &secp,
+            &blinding_key.public_key(&secp),
+        );
+
+        let generator = Generator::new_unblinded(&secp, asset().into_tag());
+        let value_bf = ValueBlindingFactor::new(&mut thread_rng());
+        let value = confidential::Value::new_confidential(&secp, VALUE, generator, value_bf);
+        let script_pubkey = address.script_pubkey();
+        let mut message = asset().into_inner().to_byte_array().to_vec();
+        message.extend_from_slice(&[0; 32]);
+        let rangeproof = RangeProof::new(
+            &secp,
+            1,
+            value.commitment().unwrap(),
+            VALUE,
+            value_bf.into_inner(),
+            &message,
+            script_pubkey.as_bytes(),
+            shared_secret,
+            0,
+            52,
+            generator,
+        )
+        .unwrap();
+
+        let output = TxOut {
+            asset: confidential::Asset::Explicit(asset()),
+            value,
+            nonce,
+            script_pubkey,
+            witness: elements::TxOutWitness {
+                surjection_proof: None,
+                rangeproof: Some(Box::new(rangeproof)),
+            },
+        };
+
+        let (tx_hex, outpoint) = funding(output);
+        let (_, secrets) =
+            resolve_utxo(&secp, &utxo_data(tx_hex, &blinding_key), &outpoint).unwrap();
+
+        assert_eq!(secrets.asset, asset());
+        assert_eq!(secrets.value, VALUE);
+        assert_eq!(secrets.asset_bf, AssetBlindingFactor::zero());
+        assert_eq!(secrets.value_bf, value_bf);
+    }
+
+    #[test]
+    fn rejects_wrong_blinding_key() {
+        let secp = Secp256k1::new();
+        let (_, address) = confidential_address(&secp);
+        let (output, ..) = TxOut::new_not_last_confidential(
+            &mut thread_rng(),
+            &secp,
+            VALUE,
+            address,
+            asset(),
+            &[explicit_secrets(asset(), VALUE)],
+        )
+        .unwrap();
+
+        let (tx_hex, outpoint) = funding(output);
+        let wrong_key = SecretKey::new(&mut thread_rng());
+        assert!(resolve_utxo(&secp, &utxo_data(tx_hex, &wrong_key), &outpoint).is_err());
+    }
+
+    #[test]
+    fn rejects_transaction_other_than_the_one_spent() {
+        let secp = Secp256k1::new();
+        let (blinding_key, address) = confidential_address(&secp);
+        let (tx_hex, mut outpoint) = funding(address_output(&address, asset(), VALUE));
+        outpoint.vout = 1;
+        assert!(resolve_utxo(&secp, &utxo_data(tx_hex, &blinding_key), &outpoint).is_err());
+    }
+
+    fn plain_address(secp: &Secp256k1<All>) -> Address {
+        let key = SecretKey::new(&mut thread_rng());
+        Address::p2wpkh(
+            &elements::bitcoin::PublicKey::new(key.public_key(secp)),
+            None,
+            &AddressParams::LIQUID,
+        )
+    }
+
+    /// An explicit UTXO at output 0 of a made-up transaction.
+    fn explicit_utxo(
+        secp: &Secp256k1<All>,
+        txid_byte: u8,
+        asset: AssetId,
+        value: u64,
+    ) -> (String, UtxoData) {
+        let input = format!("{}:0", elements::Txid::from_byte_array([txid_byte; 32]));
+        let data = UtxoData {
+            asset: Some(asset.to_string()),
+            value: Some(value),
+            scriptpubkey: Some(hex::encode(plain_address(secp).script_pubkey().as_bytes())),
+            tx_hex: None,
+            blinding_key: None,
+            spend: None,
+        };
+        (input, data)
+    }
+
+    fn request(inputs: Vec<(String, UtxoData)>, outputs: Vec<String>) -> CreatePsetRequest {
+        let (inputs, utxos) = inputs.into_iter().unzip();
+        CreatePsetRequest {
+            inputs,
+            outputs,
+            utxos,
+            asset_id: None,
+            network: "liquid".to_string(),
+            change_address: None,
+            fee_rate: None,
+            issuances: Vec::new(),
+        }
+    }
+
+    fn issuance(input: usize, amount: u64, address: &Address) -> IssuanceRequest {
+        IssuanceRequest {
+            input,
+            amount,
+            asset_address: Some(address.to_string()),
+            token_amount: 0,
+            token_address: None,
+            contract_hash: None,
+            reissue_entropy: None,
+        }
+    }
+
+    fn extract_tx(response: &CreatePsetResponse) -> Transaction {
+        let pset: PartiallySignedTransaction =
+            deserialize(&hex::decode(&response.pset).unwrap()).unwrap();
+        pset.extract_tx().unwrap()
+    }
+
+    fn other_asset() -> AssetId {
+        AssetId::from_slice(&[7; 32]).unwrap()
+    }
+
+    #[test]
+    fn balances_each_asset_separately() {
+        let secp = Secp256k1::new();
+        let plain = plain_address(&secp);
+        let req = request(
+            vec![
+                explicit_utxo(&secp, 1, asset(), VALUE),
+                explicit_utxo(&secp, 2, other_asset(), 500),
+            ],
+            vec![
+                format!("{}:149000", plain),
+                format!("{}:500:{}", plain, other_asset()),
+                "fee:1000".to_string(),
+            ],
+        );
+
+        let response = build_pset(&req).unwrap();
+        assert_eq!(response.fee, 1000);
+
+        let tx = extract_tx(&response);
+        let amounts: Vec<(AssetId, u64)> = tx
+            .output
+            .iter()
+            .map(|output| {
+                (
+                    output.asset.explicit().unwrap(),
+                    output.value.explicit().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            amounts,
+            vec![(asset(), 149_000), (other_asset(), 500), (asset(), 1000)]
+        );
+    }
+
+    #[test]
+    fn rejects_policy_asset_surplus() {
+        let secp = Secp256k1::new();
+        let plain = plain_address(&secp);
+        let req = request(
+            vec![explicit_utxo(&secp, 1, asset(), VALUE)],
+            vec![format!("{}:100000", plain), "fee:1000".to_string()],
+        );
+
+        let error = build_pset(&req).unwrap_err();
+        assert!(error.contains("do not balance"), "{}", error);
+        assert!(error.contains(&asset().to_string()), "{}", error);
+    }
+
+    #[test]
+    fn rejects_surplus_of_another_asset() {
+        let secp = Secp256k1::new();
+        let plain = plain_address(&secp);
+        let req = request(
+            vec![
+                explicit_utxo(&secp, 1, asset(), VALUE),
+                explicit_utxo(&secp, 2, other_asset(), 500),
+            ],
+            vec![
+                format!("{}:149000", plain),
+                format!("{}:400:{}", plain, other_asset()),
+                "fee:1000".to_string(),
+            ],
+        );
+
+        let error = build_pset(&req).unwrap_err();
+        assert!(error.contains("do not balance"), "{}", error);
+        assert!(error.contains(&other_asset().to_string()), "{}", error);
+    }
+
+    #[test]
+    fn blinds_outputs_to_confidential_addresses() {
+        let secp = Secp256k1::new();
+        let (blinding_key, confidential) = confidential_address(&secp);
+        let plain = plain_address(&secp);
+        let req = request(
+            vec![explicit_utxo(&secp, 1, asset(), VALUE)],
+            vec![
+                format!("{}:100000", confidential),
+                format!("{}:49000", plain),
+                "fee:1000".to_string(),
+            ],
+        );
+
+        let response = build_pset(&req).unwrap();
+        assert_eq!(response.blinded_outputs, [0]);
+
+        let tx = extract_tx(&response);
+        assert!(tx.output[0].asset.is_confidential());
+        assert!(tx.output[0].value.is_confidential());
+        assert_eq!(tx.output[1].value, confidential::Value::Explicit(49_000));
+        assert!(tx.output[2].is_fee());
+
+        let secrets = tx.output[0].unblind(&secp, blinding_key).unwrap();
+        assert_eq!(secrets.asset, asset());
+        assert_eq!(secrets.value, 100_000);
+
+        let (spent, _) = resolve_utxo(&secp, &req.utxos[0], &tx.input[0].previous_output).unwrap();
+        tx.verify_tx_amt_proofs(&secp, &[spent]).unwrap();
+    }
+
+    #[test]
+    fn issues_asset_and_token() {
+        let secp = Secp256k1::new();
+        let plain = plain_address(&secp);
+        let mut req = request(
+            vec![explicit_utxo(&secp, 1, asset(), VALUE)],
+            vec![
+                format!("{}:{}", plain, VALUE - 1000),
+                "fee:1000".to_string(),
+            ],
+        );
+        req.issuances.push(IssuanceRequest {
+            token_amount: 1,
+            token_address: Some(plain.to_string()),
+            ..issuance(0, 5000, &plain)
+        });
+
+        let response = build_pset(&req).unwrap();
+
+        let outpoint = OutPoint::new(elements::Txid::from_byte_array([1; 32]), 0);
+        let entropy = AssetId::generate_asset_entropy(outpoint, ContractHash::all_zeros());
+        let issued = AssetId::from_entropy(entropy);
+        let token = AssetId::reissuance_token_from_entropy(entropy, false);
+        assert_eq!(response.issuances[0].asset, issued.to_string());
+        assert_eq!(response.issuances[0].token, token.to_string());
+        assert_eq!(parse_entropy(&response.issuances[0].entropy), Ok(entropy));
+
+        let tx = extract_tx(&response);
+        let asset_issuance = &tx.input[0].asset_issuance;
+        assert_eq!(asset_issuance.asset_blinding_nonce, ZERO_TWEAK);
+        assert_eq!(asset_issuance.amount, confidential::Value::Explicit(5000));
+        assert_eq!(
+            asset_issuance.inflation_keys,
+            confidential::Value::Explicit(1)
+        );
+        assert_eq!(
+            tx.output[2],
+            explicit_output(issued, plain.script_pubkey(), 5000)
+        );
+        assert_eq!(
+            tx.output[3],
+            explicit_output(token, plain.script_pubkey(), 1)
+        );
+    }
+
+    #[test]
+    fn reissues_from_a_confidential_token() {
+        let secp = Secp256k1::new();
+        let outpoint = OutPoint::new(elements::Txid::from_byte_array([9; 32]), 0);
+        let entropy = AssetId::generate_asset_entropy(outpoint, ContractHash::all_zeros());
+        let reissued = AssetId::from_entropy(entropy);
+        let token = AssetId::reissuance_token_from_entropy(entropy, false);
+
+        let (blinding_key, confidential) = confidential_address(&secp);
+        let (token_output, token_bf, ..) = TxOut::new_not_last_confidential(
+            &mut thread_rng(),
+            &secp,
+            1,
+            confidential.clone(),
+            token,
+            &[explicit_secrets(token, 1)],
+        )
+        .unwrap();
+        let (tx_hex, token_outpoint) = funding(token_output);
+
+        let plain = plain_address(&secp);
+        let mut req = request(
+            vec![
+                (
+                    format!("{}:{}", token_outpoint.txid, token_outpoint.vout),
+                    utxo_data(tx_hex, &blinding_key),
+                ),
+                explicit_utxo(&secp, 2, asset(), 10_000),
+            ],
+            vec![
+                format!("{}:1:{}", confidential, token),
+                format!("{}:9000", plain),
+                "fee:1000".to_string(),
+            ],
+        );
+        req.issuances.push(IssuanceRequest {
+            reissue_entropy: Some(entropy_hex(entropy)),
+            ..issuance(0, 700, &plain)
+        });
+
+        let response = build_pset(&req).unwrap();
+        assert_eq!(response.issuances[0].asset, reissued.to_string());
+        assert_eq!(response.issuances[0].token, token.to_string());
+        assert_eq!(response.issuances[0].entropy, entropy_hex(entropy));
+
+        let tx = extract_tx(&response);
+        let asset_issuance = &tx.input[0].asset_issuance;
+        assert_eq!(asset_issuance.asset_blinding_nonce, token_bf.into_inner());
+        assert_eq!(asset_issuance.asset_entropy, entropy.to_byte_array());
+        assert_eq!(asset_issuance.amount, confidential::Value::Explicit(700));
+        assert_eq!(asset_issuance.inflation_keys, confidential::Value::Null);
+        assert_eq!(
+            tx.output[3],
+            explicit_output(reissued, plain.script_pubkey(), 700)
+        );
+    }
+
+    #[test]
+    fn rejects_reissuance_from_an_explicit_token() {
+        let secp = Secp256k1::new();
+        let outpoint = OutPoint::new(elements::Txid::from_byte_array([9; 32]), 0);
+        let entropy = AssetId::generate_asset_entropy(outpoint, ContractHash::all_zeros());
+        let token = AssetId::reissuance_token_from_entropy(entropy, false);
+
+        let plain = plain_address(&secp);
+        let mut req = request(
+            vec![
+                explicit_utxo(&secp, 1, token, 1),
+                explicit_utxo(&secp, 2, asset(), 1000),
+            ],
+            vec![format!("{}:1:{}", plain, token), "fee:1000".to_string()],
+        );
+        req.issuances.push(IssuanceRequest {
+            reissue_entropy: Some(entropy_hex(entropy)),
+            ..issuance(0, 700, &plain)
+        });
+
+        let error = build_pset(&req).unwrap_err();
+        assert!(error.contains("confidential"), "{}", error);
+    }
+
+    #[test]
+    fn pins_issuance_input_with_change_address() {
+        let secp = Secp256k1::new();
+        let plain = plain_address(&secp);
+        // Largest-first alone would pick the second input and leave out the issuance.
+        let mut req = request(
+            vec![
+                explicit_utxo(&secp, 1, asset(), 20_000),
+                explicit_utxo(&secp, 2, asset(), VALUE),
+            ],
+            vec![format!("{}:1000", plain)],
+        );
+        req.change_address = Some(plain.to_string());
+        req.fee_rate = Some(1.0);
+        req.issuances.push(issuance(0, 5000, &plain));
+
+        let response = build_pset(&req).unwrap();
+        assert_eq!(response.selected_inputs, [req.inputs[0].clone()]);
+        assert_eq!(response.change.len(), 1);
+        assert_eq!(response.change[0].asset, asset().to_string());
+        assert_eq!(response.change[0].value, 20_000 - 1000 - response.fee);
+
+        let tx = extract_tx(&response);
+        assert!(tx.input[0].has_issuance());
+        let issued = AssetId::from_str(&response.issuances[0].asset).unwrap();
+        assert_eq!(
+            tx.output[1],
+            explicit_output(issued, plain.script_pubkey(), 5000)
+        );
+        assert!(tx.output.last().unwrap().is_fee());
+    }
+
+    #[test]
+    fn selects_more_inputs_around_a_pinned_issuance() {
+        let secp = Secp256k1::new();
+        let plain = plain_address(&secp);
+        let mut req = request(
+            vec![
+                explicit_utxo(&secp, 1, asset(), 20_000),
+                explicit_utxo(&secp, 2, asset(), VALUE),
+            ],
+            vec![format!("{}:100000", plain)],
+        );
+        req.change_address = Some(plain.to_string());
+        req.fee_rate = Some(1.0);
+        req.issuances.push(issuance(0, 5000, &plain));
+
+        let response = build_pset(&req).unwrap();
+        assert_eq!(response.selected_inputs, req.inputs);
+        assert_eq!(
+            response.change[0].value,
+            20_000 + VALUE - 100_000 - response.fee
+        );
+        assert!(extract_tx(&response).input[0].has_issuance());
+    }
+
+    #[test]
+    fn entropy_hex_uses_display_byte_order() {
+        let display = format!("{}01", "00".repeat(31));
+        let entropy = parse_entropy(&display).unwrap();
+        assert_eq!(entropy.to_byte_array()[0], 1);
+        assert_eq!(entropy_hex(entropy), display);
+        assert!(parse_entropy("abcd").is_err());
+    }
 }
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use anyhow::{Context, Result};
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use elements::{encode::deserialize, encode::serialize, pset::PartiallySignedTransaction};
+use simplicityhl::simplicity::bit_machine::{ExecTracker, FrameIter, NodeOutput};
+use simplicityhl::simplicity::elements as simplicity_elements;
+use simplicityhl::simplicity::jet::Jet;
+use simplicityhl::simplicity::jet::elements::{ElementsEnv, ElementsUtxo};
+use simplicityhl::simplicity::node::Inner;
+use simplicityhl::simplicity::{BitIter, BitMachine, RedeemNode};
+use simplicityhl::simplicity_unchained::jets::elements::{ElementsExtension, ElementsExtensionEnv};
+
+/// Genesis block hashes committed to by `sig_all_hash` and friends.
+const LIQUID_GENESIS: &str = "1466275836220db2944ca059a3a10ef6fd2ea684b0688d2c379296888a206003";
+const LIQUID_TESTNET_GENESIS: &str =
+    "a771da8e52ee6ad581ed1e9a99825e5b3b7992225534eaa2ae23244fe26ab1c1";
+
+/// Simplicity leaf version followed by the BIP341 NUMS point. Unchained programs
+/// are not committed to a tapleaf, so the control block is only a placeholder.
+const PLACEHOLDER_CONTROL_BLOCK: &str =
+    "be50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+#[derive(Deserialize)]
+pub struct DryRunPsetRequest {
+    pub pset_hex: String,
+    pub input_index: usize,
+    pub redeem_script_hex: String,
+    pub program: String,
+    pub witness: String,
+    /// `liquid`, `liquid_testnet` or `elements`, which picks the genesis hash.
+    pub network: String,
+    /// Genesis hash of the chain; required for `elements`, whose genesis
+    /// block depends on the chain parameters.
+    pub genesis_hash: Option<String>,
+}
+
+#[wasm_bindgen]
+pub fn dry_run_pset(request_json: JsValue) -> Result<JsValue, JsValue> {
+    let request: DryRunPsetRequest = serde_wasm_bindgen::from_value(request_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse request: {}", e)))?;
+
+    let genesis_hash = genesis_hash(&request.network, request.genesis_hash.as_deref())
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    let result = execute(
+        &request.pset_hex,
+        request.input_index,
+        &request.redeem_script_hex,
+        &request.program,
+        &request.witness,
+        genesis_hash,
+    );
+
+    match result {
+        Ok(output) => serde_wasm_bindgen::to_value(&output)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e))),
+        Err(e) => Err(JsValue::from_str(&e.to_string())),
+    }
+}
+
+/// Picks the genesis hash of `network`, unless one is given explicitly.
+fn genesis_hash<'a>(network: &str, explicit: Option<&'a str>) -> Result<&'a str> {
+    match (network, explicit) {
+        ("liquid" | "liquid_testnet" | "elements", Some(genesis_hash)) => Ok(genesis_hash),
+        ("liquid", None) => Ok(LIQUID_GENESIS),
+        ("liquid_testnet", None) => Ok(LIQUID_TESTNET_GENESIS),
+        ("elements", None) => Err(anyhow::anyhow!(
+            "A genesis hash is required for network elements"
+        )),
+        _ => Err(anyhow::anyhow!(
+            "Unsupported network '{}'. Supported networks are: elements, liquid, liquid_testnet.",
+            network
+        )),
+    }
+}
+
+/// Runs a satisfied program on the bit machine against input `input_index` of a PSET.
+///
+/// A failing program is not an error: the response reports `success: false`
+/// along with the failing jet call, if any. Errors are reserved for malformed input.
+pub fn execute(
+    pset_hex: &str,
+    input_index: usize,
+    redeem_script_hex: &str,
+    program_base64: &str,
+    witness_base64: &str,
+    genesis_hash_hex: &str,
+) -> Result<serde_json::Value> {
+    let pset_bytes = hex::decode(pset_hex).context("Failed to decode PSET hex")?;
+    let pset: PartiallySignedTransaction =
+        deserialize(&pset_bytes).context("Failed to deserialize PSET")?;
+
+    if input_index >= pset.inputs().len() {
+        return Err(anyhow::anyhow!(
+            "Input index {} out of bounds (PSET has {} inputs)",
+            input_index,
+            pset.inputs().len()
+        ));
+    }
+
+    let redeem_script_bytes =
+        hex::decode(redeem_script_hex).context("Failed to decode redeem script hex")?;
+
+    let program = decode_program::<ElementsExtension>(program_base64, witness_base64)?;
+
+    // simplicity-lang is built against an older `elements`, so transaction data
+    // crosses over through its consensus encoding.
+    let tx = pset.extract_tx()?;
+    let tx: simplicity_elements::Transaction = simplicity_elements::encode::deserialize(&serialize(&tx))
+        .context("Failed to convert transaction")?;
+
+    let mut utxos = Vec::with_capacity(pset.inputs().len());
+    for (i, input) in pset.inputs().iter().enumerate() {
+        let utxo = input
+            .witness_utxo
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Missing witness UTXO for input {}", i))?;
+        let utxo: simplicity_elements::TxOut =
+            simplicity_elements::encode::deserialize(&serialize(utxo))
+                .with_context(|| format!("Failed to convert witness UTXO for input {}", i))?;
+        utxos.push(ElementsUtxo::from(utxo));
+    }
+
+    let control_block = simplicity_elements::taproot::ControlBlock::from_slice(
+        &hex::decode(PLACEHOLDER_CONTROL_BLOCK).expect("constant is valid hex"),
+    )
+    .context("Failed to build control block")?;
+    let genesis_hash = genesis_hash_hex
+        .parse::<simplicity_elements::BlockHash>()
+        .context("Invalid genesis hash")?;
+
+    let elements_env = ElementsEnv::new(
+        Arc::new(tx),
+        utxos,
+        input_index as u32,
+        program.cmr(),
+        control_block,
+        None,
+        genesis_hash,
+    );
+    let env = ElementsExtensionEnv::new(elements_env, redeem_script_bytes);
+
+    Ok(run(&program, &env, input_index))
+}
+
+/// Decodes a program and its witness from the base64 encoding produced by `compile`.
+pub(crate) fn decode_program<J: Jet>(
+    program_base64: &str,
+    witness_base64: &str,
+) -> Result<Arc<RedeemNode<J>>> {
+    let program_bytes = STANDARD
+        .decode(program_base64)
+        .context("Failed to decode program base64")?;
+    let witness_bytes = STANDARD
+        .decode(witness_base64)
+        .context("Failed to decode witness base64")?;
+
+    RedeemNode::<J>::decode(
+        BitIter::from(program_bytes.as_slice()),
+        BitIter::from(witness_bytes.as_slice()),
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to decode program: {}", e))
+}
+
+/// Executes `program` in `env` and describes the outcome as JSON.
+///
+/// Simplicity has no cost meter at run time, so `static_cost_bound` is the
+/// program's static cost bound in milliweight units, the same for every run.
+/// `jet_calls` counts the jets this run called.
+pub(crate) fn run<J: Jet>(
+    program: &Arc<RedeemNode<J>>,
+    env: &J::Environment,
+    input_index: usize,
+) -> serde_json::Value {
+    let cost_bound = program.bounds().cost;
+    let mut tracker = FailedJetTracker::default();
+
+    let result = BitMachine::for_program(program)
+        .map_err(|e| e.to_string())
+        .and_then(|mut machine| {
+            machine
+                .exec_with_tracker(program, env, &mut tracker)
+                .map_err(|e| e.to_string())
+        });
+
+    // A failed `jet::verify` is how a SimplicityHL `assert!` fails.
+    let error = result.err().map(|error| match &tracker.failure {
+        Some(failure) if failure.jet == "verify" => format!(
+            "{}: assertion failed (call {} to jet::verify, jet call {} of the run)",
+            error, failure.call, failure.position
+        ),
+        Some(failure) => format!(
+            "{}: jet::{} failed on its call {} (jet call {} of the run) with input {}",
+            error, failure.jet, failure.call, failure.position, failure.input
+        ),
+        None => error,
+    });
+
+    serde_json::json!({
+        "success": error.is_none(),
+        "error": error,
+        "failed_jet": tracker.failure.as_ref().map(|failure| &failure.jet),
+        "failure": tracker.failure,
+        "static_cost_bound": cost_bound.to_string(),
+        "jet_calls": tracker.position,
+        "input_index": input_index,
+    })
+}
+
+/// Where in the run a jet failed.
+#[derive(Debug, Serialize)]
+pub(crate) struct JetFailure {
+    jet: String,
+    /// 1-based count of calls to this jet up to and including the failing one,
+    /// which tells apart repeated assertions.
+    call: usize,
+    /// 1-based count of all jet calls up to and including the failing one.
+    position: usize,
+    /// Input bits of the failing call.
+    input: String,
+}
+
+/// Counts jet calls during execution and remembers the one that failed.
+#[derive(Default)]
+pub(crate) struct FailedJetTracker {
+    calls: HashMap<String, usize>,
+    position: usize,
+    failure: Option<JetFailure>,
+}
+
+impl<J: Jet> ExecTracker<J> for FailedJetTracker {
+    fn visit_node(&mut self, node: &RedeemNode<J>, input: FrameIter, output: NodeOutput) {
+        let Inner::Jet(jet) = node.inner() else {
+            return;
+        };
+        let name = jet.to_string();
+        self.position += 1;
+        let call = self.calls.entry(name.clone()).or_default();
+        *call += 1;
+
+        if let NodeOutput::JetFailed = output {
+            self.failure = Some(JetFailure {
+                jet: name,
+                call: *call,
+                position: self.position,
+                input: input.map(|bit| if bit { '1' } else { '0' }).collect(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elements::{AssetId, LockTime, Transaction, TxIn, TxOut, confidential};
+    use simplicityhl::parse::ParseFromStr;
+    use simplicityhl::str::WitnessName;
+    use simplicityhl::{ResolvedType, Value, WitnessValues};
+
+    const PROGRAM: &str = "fn main() {
+    let x: u32 = witness::X;
+    assert!(jet::eq_32(x, 7));
+}";
+
+    /// Compiles [`PROGRAM`] with witness `X = x`, returning the base64 program
+    /// and witness as `compile` does.
+    fn compile(x: u32) -> (String, String) {
+        let compiled = simplicityhl::TemplateProgram::<ElementsExtension>::new(PROGRAM.to_string())
+            .unwrap()
+            .instantiate(simplicityhl::Arguments::default(), false)
+            .unwrap();
+        let ty = ResolvedType::parse_from_str("u32").unwrap();
+        let value = Value::parse_from_str(&x.to_string(), &ty).unwrap();
+        let witness = WitnessValues::from(HashMap::from([(
+            WitnessName::from_str_unchecked("X"),
+            value,
+        )]));
+        let satisfied = compiled.satisfy(witness).unwrap();
+
+        let (mut program, mut witness) = (Vec::new(), Vec::new());
+        satisfied
+            .redeem()
+            .encode_with_witness(&mut program, &mut witness)
+            .unwrap();
+        (STANDARD.encode(program), STANDARD.encode(witness))
+    }
+
+    fn explicit_output(value: u64) -> TxOut {
+        TxOut {
+            asset: confidential::Asset::Explicit(AssetId::from_slice(&[1; 32]).unwrap()),
+            value: confidential::Value::Explicit(value),
+            ..TxOut::default()
+        }
+    }
+
+    fn pset_hex() -> String {
+        let tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![explicit_output(1_000)],
+        };
+        let mut pset = PartiallySignedTransaction::from_tx(tx);
+        pset.inputs_mut()[0].witness_utxo = Some(explicit_output(1_000));
+        hex::encode(serialize(&pset))
+    }
+
+    fn dry_run(program: &str, witness: &str) -> Result<serde_json::Value> {
+        execute(
+            &pset_hex(),
+            0,
+            "51",
+            program,
+            witness,
+            LIQUID_TESTNET_GENESIS,
+        )
+    }
+
+    #[test]
+    fn runs_satisfied_program() {
+        let (program, witness) = compile(7);
+        let result = dry_run(&program, &witness).unwrap();
+
+        assert_eq!(result["success"], true);
+        assert!(result["error"].is_null());
+        assert!(result["failure"].is_null());
+        assert!(result["jet_calls"].as_u64().unwrap() >= 2);
+    }
+
+    #[test]
+    fn reports_failed_assertion() {
+        let (program, witness) = compile(8);
+        let result = dry_run(&program, &witness).unwrap();
+
+        assert_eq!(result["success"], false);
+        assert_eq!(result["failed_jet"], "verify");
+        assert_eq!(result["failure"]["call"], 1);
+        let error = result["error"].as_str().unwrap();
+        assert!(error.contains("assertion failed"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_witness_of_wrong_shape() {
+        let (program, _) = compile(7);
+        let error = dry_run(&program, "").unwrap_err();
+        assert!(
+            error.to_string().contains("Failed to decode program"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn picks_genesis_hash_from_network() {
+        assert_eq!(genesis_hash("liquid", None).unwrap(), LIQUID_GENESIS);
+        assert_eq!(
+            genesis_hash("liquid_testnet", None).unwrap(),
+            LIQUID_TESTNET_GENESIS
+        );
+        assert_eq!(
+            genesis_hash("elements", Some(LIQUID_GENESIS)).unwrap(),
+            LIQUID_GENESIS
+        );
+        assert!(genesis_hash("elements", None).is_err());
+        assert!(genesis_hash("bitcoin", None).is_err());
+    }
+}
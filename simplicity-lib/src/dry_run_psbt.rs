@@ -0,0 +1,172 @@
+use anyhow::{Context, Result};
+use wasm_bindgen::prelude::*;
+use serde::Deserialize;
+use elements::bitcoin::{ScriptBuf, psbt::Psbt};
+use simplicityhl::simplicity_unchained::jets::bitcoin::{CoreExtension, CoreExtensionEnv};
+
+use crate::dry_run::{decode_program, run};
+
+#[derive(Deserialize)]
+pub struct DryRunPsbtRequest {
+    pub psbt_hex: String,
+    pub input_index: usize,
+    pub redeem_script_hex: String,
+    pub program: String,
+    pub witness: String,
+}
+
+#[wasm_bindgen]
+pub fn dry_run_psbt(request_json: JsValue) -> Result<JsValue, JsValue> {
+    let request: DryRunPsbtRequest = serde_wasm_bindgen::from_value(request_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse request: {}", e)))?;
+
+    let result = execute(
+        &request.psbt_hex,
+        request.input_index,
+        &request.redeem_script_hex,
+        &request.program,
+        &request.witness,
+    );
+
+    match result {
+        Ok(output) => serde_wasm_bindgen::to_value(&output)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e))),
+        Err(e) => Err(JsValue::from_str(&e.to_string())),
+    }
+}
+
+/// Runs a satisfied `CoreExtension` program on the bit machine against input
+/// `input_index` of a PSBT, with `redeem_script_hex` exposed to the Unchained
+/// script-introspection jets.
+pub fn execute(
+    psbt_hex: &str,
+    input_index: usize,
+    redeem_script_hex: &str,
+    program_base64: &str,
+    witness_base64: &str,
+) -> Result<serde_json::Value> {
+    let psbt_bytes = hex::decode(psbt_hex).context("Failed to decode PSBT hex")?;
+    let psbt: Psbt = Psbt::deserialize(&psbt_bytes).context("Failed to deserialize PSBT")?;
+
+    if input_index >= psbt.inputs.len() {
+        return Err(anyhow::anyhow!(
+            "Input index {} out of bounds (PSBT has {} inputs)",
+            input_index,
+            psbt.inputs.len()
+        ));
+    }
+
+    let redeem_script_bytes =
+        hex::decode(redeem_script_hex).context("Failed to decode redeem script hex")?;
+    let redeem_script = ScriptBuf::from_bytes(redeem_script_bytes);
+
+    let program = decode_program::<CoreExtension>(program_base64, witness_base64)?;
+
+    let mut prevouts = Vec::with_capacity(psbt.inputs.len());
+    for (i, input) in psbt.inputs.iter().enumerate() {
+        let utxo = input
+            .witness_utxo
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Missing witness UTXO for input {}", i))?;
+        prevouts.push(utxo.clone());
+    }
+
+    let tx = psbt.clone().extract_tx_unchecked_fee_rate();
+
+    let env = CoreExtensionEnv::new(tx, prevouts, input_index as u32, redeem_script);
+
+    Ok(run(&program, &env, input_index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+    use elements::bitcoin::{Amount, Transaction, TxIn, TxOut, absolute, transaction};
+    use simplicityhl::parse::ParseFromStr;
+    use simplicityhl::str::WitnessName;
+    use simplicityhl::{ResolvedType, Value, WitnessValues};
+    use std::collections::HashMap;
+
+    const PROGRAM: &str = "fn main() {
+    let x: u32 = witness::X;
+    assert!(jet::eq_32(x, 7));
+}";
+
+    /// Compiles [`PROGRAM`] with witness `X = x`, returning the base64 program
+    /// and witness as `compile` does.
+    fn compile(x: u32) -> (String, String) {
+        let compiled = simplicityhl::TemplateProgram::<CoreExtension>::new(PROGRAM.to_string())
+            .unwrap()
+            .instantiate(simplicityhl::Arguments::default(), false)
+            .unwrap();
+        let ty = ResolvedType::parse_from_str("u32").unwrap();
+        let value = Value::parse_from_str(&x.to_string(), &ty).unwrap();
+        let witness = WitnessValues::from(HashMap::from([(
+            WitnessName::from_str_unchecked("X"),
+            value,
+        )]));
+        let satisfied = compiled.satisfy(witness).unwrap();
+
+        let (mut program, mut witness) = (Vec::new(), Vec::new());
+        satisfied
+            .redeem()
+            .encode_with_witness(&mut program, &mut witness)
+            .unwrap();
+        (STANDARD.encode(program), STANDARD.encode(witness))
+    }
+
+    fn output(value: u64) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: ScriptBuf::new(),
+        }
+    }
+
+    fn psbt_hex() -> String {
+        let tx = Transaction {
+            version: transaction::Version::TWO,
+            lock_time: absolute::LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![output(9_000)],
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs[0].witness_utxo = Some(output(10_000));
+        hex::encode(psbt.serialize())
+    }
+
+    fn dry_run(program: &str, witness: &str) -> Result<serde_json::Value> {
+        execute(&psbt_hex(), 0, "51", program, witness)
+    }
+
+    #[test]
+    fn runs_satisfied_program() {
+        let (program, witness) = compile(7);
+        let result = dry_run(&program, &witness).unwrap();
+
+        assert_eq!(result["success"], true);
+        assert!(result["error"].is_null());
+        assert!(result["failure"].is_null());
+        assert!(result["jet_calls"].as_u64().unwrap() >= 2);
+    }
+
+    #[test]
+    fn reports_failed_assertion() {
+        let (program, witness) = compile(8);
+        let result = dry_run(&program, &witness).unwrap();
+
+        assert_eq!(result["success"], false);
+        assert_eq!(result["failed_jet"], "verify");
+        assert_eq!(result["failure"]["call"], 1);
+        let error = result["error"].as_str().unwrap();
+        assert!(error.contains("assertion failed"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_input_index_out_of_bounds() {
+        let (program, witness) = compile(7);
+        let error = execute(&psbt_hex(), 1, "51", &program, &witness).unwrap_err();
+        assert!(error.to_string().contains("out of bounds"), "{}", error);
+    }
+}
@@ -0,0 +1,330 @@
+use anyhow::{Context, Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use elements::encode::serialize;
+use serde::Deserialize;
+
+/// Outputs below this many satoshis are dropped into the fee instead of being created.
+pub const DUST_LIMIT: u64 = 546;
+
+/// Weight of an input without its witness: outpoint, empty `scriptSig` length
+/// and sequence.
+const INPUT_BASE_WEIGHT: u64 = (32 + 4 + 1 + 4) * 4;
+
+/// Witness of the 2-of-2 `OP_CHECKMULTISIG` redeem script: item count, dummy,
+/// two DER signatures and the 71-byte script, each with a length prefix.
+const P2WSH_2OF2_WITNESS_WEIGHT: u64 = 1 + 1 + 2 * (1 + 72) + (1 + 71);
+/// Witness of a P2WPKH spend: item count, signature and compressed key.
+const P2WPKH_WITNESS_WEIGHT: u64 = 1 + (1 + 72) + (1 + 33);
+/// Witness of a taproot key-path spend with a default-sighash signature.
+const P2TR_KEY_PATH_WITNESS_WEIGHT: u64 = 1 + (1 + 64);
+
+/// Elements input witnesses also carry empty issuance range proofs and an
+/// empty peg-in witness, one length byte each.
+const ELEMENTS_INPUT_WITNESS_OVERHEAD: u64 = 3;
+/// Elements output witnesses carry a surjection proof and a range proof,
+/// one length byte each when empty.
+const ELEMENTS_OUTPUT_WITNESS_OVERHEAD: u64 = 2;
+/// Extra weight of blinding an output that names its blinding key in the
+/// nonce: a value commitment in place of the explicit value, plus a 52-bit
+/// range proof and a surjection proof over three of up to eight inputs in
+/// place of the empty proofs.
+const ELEMENTS_BLINDING_WEIGHT: u64 = (33 - 9) * 4 + (3 + 4174 - 1) + (1 + 131 - 1);
+/// Segwit marker and flag bytes of a Bitcoin transaction.
+const BITCOIN_SEGWIT_OVERHEAD: u64 = 2;
+
+/// Chain a transaction is built for, which decides how inputs and outputs are serialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chain {
+    Elements,
+    Bitcoin,
+}
+
+/// How an input will be spent, for estimating its witness.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum InputSpend {
+    /// The Simplicity Unchained 2-of-2 redeem script, signed by the user and
+    /// by the Unchained service once the program has run. A template with a
+    /// recovery path gives its hex `witness_script`, and `recovery` when it is
+    /// spent through that path.
+    Multisig2of2 {
+        #[serde(default)]
+        witness_script: Option<String>,
+        #[serde(default)]
+        recovery: bool,
+    },
+    /// A Simplicity taproot script-path spend on Elements, with the
+    /// base64-encoded program and witness produced by `compile`.
+    Simplicity { program: String, witness: String },
+}
+
+/// Predicted size and fee of a transaction once all of its inputs are signed.
+#[derive(Debug, Clone, Copy)]
+pub struct FeeEstimate {
+    pub weight: u64,
+    pub vsize: u64,
+    pub fee: u64,
+    /// `fee / vsize` in sat/vB.
+    pub fee_rate: f64,
+}
+
+impl FeeEstimate {
+    pub fn new(weight: u64, fee: u64) -> Self {
+        let vsize = weight.div_ceil(4);
+        FeeEstimate {
+            weight,
+            vsize,
+            fee,
+            fee_rate: fee as f64 / vsize as f64,
+        }
+    }
+}
+
+/// Estimates the witness weight of spending an output locked by `script_pubkey`.
+///
+/// Without a `spend`, P2WSH outputs are assumed to be the Simplicity Unchained
+/// 2-of-2, the only P2WSH script the demo spends.
+pub fn witness_weight(
+    chain: Chain,
+    script_pubkey: &[u8],
+    spend: Option<&InputSpend>,
+) -> Result<u64> {
+    let witness = match spend {
+        Some(InputSpend::Multisig2of2 {
+            witness_script: None,
+            recovery: false,
+        }) => P2WSH_2OF2_WITNESS_WEIGHT,
+        Some(InputSpend::Multisig2of2 {
+            witness_script: None,
+            recovery: true,
+        }) => {
+            return Err(anyhow!(
+                "A recovery spend needs the `witness_script` of the template"
+            ));
+        }
+        Some(InputSpend::Multisig2of2 {
+            witness_script: Some(script_hex),
+            recovery,
+        }) => {
+            let script_len = hex::decode(script_hex)
+                .context("Failed to decode witness script hex")?
+                .len();
+
+            // The recovery path leaves the 2-of-2 signatures and its dummy
+            // empty, so `OP_IFDUP OP_NOTIF` takes the timelocked branch.
+            let signatures = if *recovery {
+                (1 + 72) + 3
+            } else {
+                1 + 2 * (1 + 72)
+            };
+            1 + signatures + var_int_len(script_len) + script_len as u64
+        }
+        Some(InputSpend::Simplicity { program, witness }) => {
+            if chain != Chain::Elements {
+                return Err(anyhow!(
+                    "Simplicity script-path spends only exist on Elements"
+                ));
+            }
+            let program_len = STANDARD
+                .decode(program)
+                .context("Failed to decode program base64")?
+                .len();
+            let witness_len = STANDARD
+                .decode(witness)
+                .context("Failed to decode witness base64")?
+                .len();
+
+            // Witness data, program, the CMR as the leaf script and a single-leaf control block.
+            1 + [witness_len, program_len, 32, 33]
+                .iter()
+                .map(|len| var_int_len(*len) + *len as u64)
+                .sum::<u64>()
+        }
+        None => match script_pubkey {
+            [0x00, 0x14, ..] if script_pubkey.len() == 22 => P2WPKH_WITNESS_WEIGHT,
+            [0x51, 0x20, ..] if script_pubkey.len() == 34 => P2TR_KEY_PATH_WITNESS_WEIGHT,
+            _ => P2WSH_2OF2_WITNESS_WEIGHT,
+        },
+    };
+
+    Ok(match chain {
+        Chain::Elements => witness + ELEMENTS_INPUT_WITNESS_OVERHEAD,
+        Chain::Bitcoin => witness,
+    })
+}
+
+/// Weight an input adds to a transaction, given the weight of its witness.
+pub fn input_weight(witness_weight: u64) -> u64 {
+    INPUT_BASE_WEIGHT + witness_weight
+}
+
+/// Weight an Elements output adds to a transaction once blinded, witness included.
+pub fn elements_output_weight(output: &elements::TxOut) -> u64 {
+    let blinding = if is_unblinded_confidential(output) {
+        ELEMENTS_BLINDING_WEIGHT
+    } else {
+        0
+    };
+    serialize(output).len() as u64 * 4 + ELEMENTS_OUTPUT_WITNESS_OVERHEAD + blinding
+}
+
+/// Weight of an unsigned Elements transaction once its inputs carry
+/// `input_witness_weight` in total and its outputs to confidential addresses
+/// are blinded.
+pub fn elements_tx_weight(tx: &elements::Transaction, input_witness_weight: u64) -> u64 {
+    let blinded = tx
+        .output
+        .iter()
+        .filter(|output| is_unblinded_confidential(output))
+        .count() as u64;
+
+    tx.weight() as u64
+        + input_witness_weight
+        + ELEMENTS_OUTPUT_WITNESS_OVERHEAD * tx.output.len() as u64
+        + ELEMENTS_BLINDING_WEIGHT * blinded
+}
+
+/// Weight of an unsigned Bitcoin transaction once its inputs carry
+/// `input_witness_weight` in total.
+pub fn bitcoin_tx_weight(tx: &elements::bitcoin::Transaction, input_witness_weight: u64) -> u64 {
+    tx.weight().to_wu() + input_witness_weight + BITCOIN_SEGWIT_OVERHEAD
+}
+
+/// Rejects fee rates that would silently become a zero or absurd fee once
+/// converted to satoshis.
+pub fn check_fee_rate(fee_rate: f64) -> Result<()> {
+    if !fee_rate.is_finite() || fee_rate <= 0.0 {
+        return Err(anyhow!(
+            "Fee rate must be a positive number of sat/vB, got {}",
+            fee_rate
+        ));
+    }
+    Ok(())
+}
+
+/// Fee in satoshis for `weight` at `fee_rate` sat/vB, rounded up.
+pub fn fee_for_weight(weight: u64, fee_rate: f64) -> u64 {
+    (weight.div_ceil(4) as f64 * fee_rate).ceil() as u64
+}
+
+/// An explicit output holding a blinding key in its nonce, still to be blinded.
+fn is_unblinded_confidential(output: &elements::TxOut) -> bool {
+    output.value.is_explicit() && output.nonce.is_confidential()
+}
+
+fn var_int_len(len: usize) -> u64 {
+    match len {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        _ => 5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::{multisig_template, parse_script};
+    use elements::bitcoin::Witness;
+
+    /// A DER signature with its sighash byte, at the size the estimates assume.
+    const SIGNATURE: &[u8] = &[0x30; 72];
+    const KEY_1: &str = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    const KEY_2: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+    const KEY_3: &str = "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9";
+
+    /// A P2WSH script pubkey; only its type matters to the estimates.
+    fn p2wsh() -> Vec<u8> {
+        let mut script_pubkey = vec![0x00, 0x20];
+        script_pubkey.extend([0; 32]);
+        script_pubkey
+    }
+
+    fn witness_size(items: &[&[u8]]) -> u64 {
+        Witness::from_slice(items).size() as u64
+    }
+
+    fn template(recovery: Option<(&str, u16)>) -> Vec<u8> {
+        let (asm, _) = multisig_template(KEY_1, KEY_2, recovery).unwrap();
+        parse_script(&asm).unwrap().into_bytes()
+    }
+
+    fn spend(script: &[u8], recovery: bool) -> InputSpend {
+        InputSpend::Multisig2of2 {
+            witness_script: Some(hex::encode(script)),
+            recovery,
+        }
+    }
+
+    #[test]
+    fn fee_for_weight_rounds_up() {
+        assert_eq!(fee_for_weight(400, 1.0), 100);
+        assert_eq!(fee_for_weight(401, 1.0), 101);
+        assert_eq!(fee_for_weight(400, 1.5), 150);
+        assert_eq!(fee_for_weight(404, 1.1), 112);
+    }
+
+    #[test]
+    fn rejects_unusable_fee_rates() {
+        for fee_rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(check_fee_rate(fee_rate).is_err(), "{}", fee_rate);
+        }
+        assert!(check_fee_rate(0.1).is_ok());
+    }
+
+    #[test]
+    fn estimates_p2wpkh_witness() {
+        let mut script_pubkey = vec![0x00, 0x14];
+        script_pubkey.extend([0; 20]);
+        let expected = witness_size(&[SIGNATURE, &[0x02; 33]]);
+
+        assert_eq!(
+            witness_weight(Chain::Bitcoin, &script_pubkey, None).unwrap(),
+            expected
+        );
+        assert_eq!(
+            witness_weight(Chain::Elements, &script_pubkey, None).unwrap(),
+            expected + ELEMENTS_INPUT_WITNESS_OVERHEAD
+        );
+    }
+
+    #[test]
+    fn estimates_2of2_witness() {
+        let script = template(None);
+        let expected = witness_size(&[&[], SIGNATURE, SIGNATURE, &script]);
+
+        assert_eq!(
+            witness_weight(Chain::Bitcoin, &p2wsh(), None).unwrap(),
+            expected
+        );
+        assert_eq!(
+            witness_weight(Chain::Bitcoin, &p2wsh(), Some(&spend(&script, false))).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn estimates_recovery_template_witnesses() {
+        let script = template(Some((KEY_3, 144)));
+
+        let cooperative = witness_size(&[&[], SIGNATURE, SIGNATURE, &script]);
+        assert_eq!(
+            witness_weight(Chain::Bitcoin, &p2wsh(), Some(&spend(&script, false))).unwrap(),
+            cooperative
+        );
+
+        // Empty 2-of-2 signatures make OP_CHECKMULTISIG push false, so
+        // OP_IFDUP OP_NOTIF runs the recovery branch.
+        let recovery = witness_size(&[SIGNATURE, &[], &[], &[], &script]);
+        assert_eq!(
+            witness_weight(Chain::Bitcoin, &p2wsh(), Some(&spend(&script, true))).unwrap(),
+            recovery
+        );
+
+        let without_script = InputSpend::Multisig2of2 {
+            witness_script: None,
+            recovery: true,
+        };
+        assert!(witness_weight(Chain::Bitcoin, &p2wsh(), Some(&without_script)).is_err());
+    }
+}
@@ -0,0 +1,1206 @@
+use anyhow::{Context, Result};
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use elements::hashes::{Hash, hash160, ripemd160, sha1, sha256, sha256d};
+use elements::secp256k1_zkp::{Message, PublicKey, Secp256k1, ecdsa::Signature};
+use elements::{
+    EcdsaSighashType, Transaction, confidential, encode::deserialize,
+    pset::PartiallySignedTransaction, script::Script, sighash::SighashCache,
+};
+
+use crate::converter::{Instruction, instructions};
+
+/// Largest element that may be pushed onto the stack.
+pub(crate) const MAX_ELEMENT_SIZE: usize = 520;
+/// Largest number of non-push opcodes a script may contain.
+pub(crate) const MAX_OPS: usize = 201;
+/// Largest combined size of the main and alt stacks.
+pub(crate) const MAX_STACK_SIZE: usize = 1000;
+/// Consensus limit on legacy and segwit v0 script size.
+pub(crate) const MAX_SCRIPT_SIZE: usize = 10_000;
+
+/// `nSequence` bit that turns relative locktime off for an input.
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+/// `nSequence` bit that switches a relative locktime from blocks to 512-second units.
+const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000_ffff;
+/// Absolute locktimes below this are block heights, the rest are timestamps.
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+#[derive(Deserialize)]
+pub struct InterpretPsetRequest {
+    pub pset_hex: String,
+    pub input_index: usize,
+    pub witness_script_hex: String,
+    /// Hex-encoded witness stack items, bottom first, without the witness script.
+    pub witness: Vec<String>,
+}
+
+#[wasm_bindgen]
+pub fn interpret_pset(request_json: JsValue) -> Result<JsValue, JsValue> {
+    let request: InterpretPsetRequest = serde_wasm_bindgen::from_value(request_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse request: {}", e)))?;
+
+    let result = execute(
+        &request.pset_hex,
+        request.input_index,
+        &request.witness_script_hex,
+        &request.witness,
+    );
+
+    match result {
+        Ok(output) => serde_wasm_bindgen::to_value(&output)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e))),
+        Err(e) => Err(JsValue::from_str(&e.to_string())),
+    }
+}
+
+/// Runs a P2WSH witness script against input `input_index` of a PSET and
+/// returns the stack after every opcode.
+///
+/// As with `dry_run`, a failing script is reported in the trace rather than
+/// as an error.
+pub fn execute(
+    pset_hex: &str,
+    input_index: usize,
+    witness_script_hex: &str,
+    witness_hex: &[String],
+) -> Result<serde_json::Value> {
+    let pset_bytes = hex::decode(pset_hex).context("Failed to decode PSET hex")?;
+    let pset: PartiallySignedTransaction =
+        deserialize(&pset_bytes).context("Failed to deserialize PSET")?;
+
+    if input_index >= pset.inputs().len() {
+        return Err(anyhow::anyhow!(
+            "Input index {} out of bounds (PSET has {} inputs)",
+            input_index,
+            pset.inputs().len()
+        ));
+    }
+
+    let witness_script =
+        Script::from(hex::decode(witness_script_hex).context("Failed to decode witness script hex")?);
+    let witness = decode_witness(witness_hex)?;
+
+    let utxo = pset.inputs()[input_index]
+        .witness_utxo
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Missing witness UTXO for input {}", input_index))?;
+
+    if utxo.script_pubkey.is_v0_p2wsh() && utxo.script_pubkey != witness_script.to_v0_p2wsh() {
+        return Err(anyhow::anyhow!(
+            "Witness script does not match the P2WSH output spent by input {}",
+            input_index
+        ));
+    }
+
+    let tx = pset.extract_tx()?;
+    let context = PsetContext {
+        tx: &tx,
+        input_index,
+        value: utxo.value,
+    };
+
+    let trace = interpret(witness_script.as_bytes(), witness, &context)?;
+
+    serde_json::to_value(trace).context("Failed to serialize trace")
+}
+
+/// Decodes hex witness items as sent by the frontend.
+pub(crate) fn decode_witness(witness_hex: &[String]) -> Result<Vec<Vec<u8>>> {
+    witness_hex
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            hex::decode(item).with_context(|| format!("Failed to decode witness item {} hex", i))
+        })
+        .collect()
+}
+
+/// Spending transaction data the interpreter needs for signature and timelock opcodes.
+pub trait SpendContext {
+    /// Checks a DER signature with its trailing sighash byte against `script_code`.
+    fn check_signature(&self, signature: &[u8], public_key: &[u8], script_code: &[u8]) -> bool;
+    fn lock_time(&self) -> u32;
+    fn sequence(&self) -> u32;
+    fn version(&self) -> u32;
+}
+
+struct PsetContext<'a> {
+    tx: &'a Transaction,
+    input_index: usize,
+    value: confidential::Value,
+}
+
+impl SpendContext for PsetContext<'_> {
+    fn check_signature(&self, signature: &[u8], public_key: &[u8], script_code: &[u8]) -> bool {
+        let Some((&hash_type, der)) = signature.split_last() else {
+            return false;
+        };
+        let Ok(hash_type) = EcdsaSighashType::from_standard(hash_type as u32) else {
+            return false;
+        };
+
+        let sighash = SighashCache::new(self.tx).segwitv0_sighash(
+            self.input_index,
+            &Script::from(script_code.to_vec()),
+            self.value,
+            hash_type,
+        );
+
+        verify_ecdsa(sighash.to_byte_array(), der, public_key)
+    }
+
+    fn lock_time(&self) -> u32 {
+        self.tx.lock_time.to_consensus_u32()
+    }
+
+    fn sequence(&self) -> u32 {
+        self.tx.input[self.input_index].sequence.to_consensus_u32()
+    }
+
+    fn version(&self) -> u32 {
+        self.tx.version
+    }
+}
+
+/// Verifies a DER-encoded ECDSA signature over `digest`, accepting high-S
+/// signatures the way consensus does.
+pub(crate) fn verify_ecdsa(digest: [u8; 32], der: &[u8], public_key: &[u8]) -> bool {
+    let (Ok(mut signature), Ok(public_key)) =
+        (Signature::from_der(der), PublicKey::from_slice(public_key))
+    else {
+        return false;
+    };
+    signature.normalize_s();
+
+    Secp256k1::verification_only()
+        .verify_ecdsa(&Message::from_digest(digest), &signature, &public_key)
+        .is_ok()
+}
+
+/// Stacks after a single opcode. Stacks are hex-encoded, bottom first.
+#[derive(Debug, Serialize)]
+pub struct Step {
+    pub index: usize,
+    pub offset: usize,
+    pub opcode: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// False when the opcode sits in a branch that is not taken.
+    pub executed: bool,
+    pub stack: Vec<String>,
+    pub alt_stack: Vec<String>,
+}
+
+/// Outcome of running a witness script, with every step leading up to it.
+#[derive(Debug, Serialize)]
+pub struct Trace {
+    pub success: bool,
+    pub error: Option<String>,
+    /// Index into `steps` of the opcode that failed, if the failure is tied to one.
+    pub failed_step: Option<usize>,
+    pub initial_stack: Vec<String>,
+    pub steps: Vec<Step>,
+    pub final_stack: Vec<String>,
+}
+
+/// Executes a segwit v0 witness script on top of `witness`.
+///
+/// Besides the consensus rules this enforces the MINIMALIF, MINIMALDATA
+/// number encoding, NULLDUMMY and NULLFAIL policies, since a spend breaking
+/// them will not be relayed. Only malformed script bytes are an error; script
+/// failures are reported in the returned [`Trace`].
+pub fn interpret<C: SpendContext>(
+    script: &[u8],
+    witness: Vec<Vec<u8>>,
+    context: &C,
+) -> Result<Trace> {
+    let instructions = instructions(script)?;
+    let initial_stack = encode_stack(&witness);
+
+    let mut machine = Machine {
+        context,
+        script,
+        stack: witness,
+        alt_stack: Vec::new(),
+        exec: Vec::new(),
+        op_count: 0,
+        code_separator: 0,
+    };
+    let mut steps = Vec::with_capacity(instructions.len());
+
+    let mut failure = if script.len() > MAX_SCRIPT_SIZE {
+        Some((None, format!("script is {} bytes, more than {}", script.len(), MAX_SCRIPT_SIZE)))
+    } else if let Some(i) = machine.stack.iter().position(|item| item.len() > MAX_ELEMENT_SIZE) {
+        Some((None, format!("witness item {} is larger than {} bytes", i, MAX_ELEMENT_SIZE)))
+    } else {
+        None
+    };
+
+    if failure.is_none() {
+        for (index, instruction) in instructions.iter().enumerate() {
+            let executed = machine.executing();
+            let result = machine.step(instruction);
+
+            let name = instruction
+                .name()
+                .unwrap_or_else(|| format!("OP_UNKNOWN_0x{:02x}", instruction.opcode));
+            steps.push(Step {
+                index,
+                offset: instruction.offset,
+                opcode: name.clone(),
+                data: instruction
+                    .data
+                    .filter(|_| instruction.opcode != 0x00)
+                    .map(hex::encode),
+                executed,
+                stack: encode_stack(&machine.stack),
+                alt_stack: encode_stack(&machine.alt_stack),
+            });
+
+            if let Err(message) = result {
+                failure = Some((
+                    Some(index),
+                    format!("{} at offset {}: {}", name, instruction.offset, message),
+                ));
+                break;
+            }
+        }
+    }
+
+    if failure.is_none() {
+        failure = if !machine.exec.is_empty() {
+            Some((None, "unbalanced conditional: missing OP_ENDIF".to_string()))
+        } else if machine.stack.len() != 1 {
+            Some((
+                None,
+                format!(
+                    "script must leave exactly one item on the stack, found {}",
+                    machine.stack.len()
+                ),
+            ))
+        } else if !cast_to_bool(&machine.stack[0]) {
+            Some((None, "script finished with a false value on the stack".to_string()))
+        } else {
+            None
+        };
+    }
+
+    let (failed_step, error) = match failure {
+        Some((step, message)) => (step, Some(message)),
+        None => (None, None),
+    };
+
+    Ok(Trace {
+        success: error.is_none(),
+        error,
+        failed_step,
+        initial_stack,
+        steps,
+        final_stack: encode_stack(&machine.stack),
+    })
+}
+
+fn encode_stack(stack: &[Vec<u8>]) -> Vec<String> {
+    stack.iter().map(hex::encode).collect()
+}
+
+type StepResult = std::result::Result<(), String>;
+
+struct Machine<'a, C: SpendContext> {
+    context: &'a C,
+    script: &'a [u8],
+    stack: Vec<Vec<u8>>,
+    alt_stack: Vec<Vec<u8>>,
+    /// One entry per open `OP_IF`, true when its branch is being executed.
+    exec: Vec<bool>,
+    op_count: usize,
+    /// Offset the script code for signature hashing starts at.
+    code_separator: usize,
+}
+
+impl<C: SpendContext> Machine<'_, C> {
+    fn executing(&self) -> bool {
+        self.exec.iter().all(|branch| *branch)
+    }
+
+    fn step(&mut self, instruction: &Instruction) -> StepResult {
+        let executing = self.executing();
+
+        if let Some(data) = instruction.data {
+            if data.len() > MAX_ELEMENT_SIZE {
+                return Err(format!("push of {} bytes exceeds {}", data.len(), MAX_ELEMENT_SIZE));
+            }
+            if executing {
+                self.stack.push(data.to_vec());
+            }
+            return self.check_stack_size();
+        }
+
+        // Everything above OP_16 counts towards the opcode limit, executed or not.
+        if instruction.opcode > 0x60 {
+            self.op_count += 1;
+            if self.op_count > MAX_OPS {
+                return Err(format!("script has more than {} opcodes", MAX_OPS));
+            }
+        }
+
+        let name = instruction.name().unwrap_or_default();
+        match name.as_str() {
+            "OP_VERIF" | "OP_VERNOTIF" => return Err("opcode is invalid".to_string()),
+            "OP_CAT" | "OP_SUBSTR" | "OP_LEFT" | "OP_RIGHT" | "OP_INVERT" | "OP_AND" | "OP_OR"
+            | "OP_XOR" | "OP_2MUL" | "OP_2DIV" | "OP_MUL" | "OP_DIV" | "OP_MOD" | "OP_LSHIFT"
+            | "OP_RSHIFT" => {
+                return Err("opcode is disabled".to_string());
+            }
+            "OP_IF" | "OP_NOTIF" => {
+                let mut branch = false;
+                if executing {
+                    let condition = self.pop()?;
+                    if condition.len() > 1 || (condition.len() == 1 && condition[0] != 1) {
+                        return Err("argument must be empty or 0x01 (MINIMALIF)".to_string());
+                    }
+                    branch = cast_to_bool(&condition) == (name == "OP_IF");
+                }
+                self.exec.push(branch);
+                return Ok(());
+            }
+            "OP_ELSE" => {
+                let branch = self
+                    .exec
+                    .last_mut()
+                    .ok_or_else(|| "OP_ELSE without a matching OP_IF".to_string())?;
+                *branch = !*branch;
+                return Ok(());
+            }
+            "OP_ENDIF" => {
+                self.exec
+                    .pop()
+                    .ok_or_else(|| "OP_ENDIF without a matching OP_IF".to_string())?;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        if !executing {
+            return Ok(());
+        }
+
+        self.execute(instruction, &name)?;
+        self.check_stack_size()
+    }
+
+    fn execute(&mut self, instruction: &Instruction, name: &str) -> StepResult {
+        match instruction.opcode {
+            0x4f | 0x51..=0x60 => {
+                self.stack.push(encode_num(instruction.opcode as i64 - 0x50));
+                return Ok(());
+            }
+            // OP_NOP1 and OP_NOP4..OP_NOP10 are reserved for soft forks and do nothing.
+            0xb0 | 0xb3..=0xb9 => return Ok(()),
+            _ => {}
+        }
+
+        match name {
+            "OP_NOP" => {}
+            "OP_VERIFY" => {
+                if !cast_to_bool(&self.pop()?) {
+                    return Err("top stack item is false".to_string());
+                }
+            }
+            "OP_RETURN" => return Err("script exits early".to_string()),
+
+            "OP_TOALTSTACK" => {
+                let item = self.pop()?;
+                self.alt_stack.push(item);
+            }
+            "OP_FROMALTSTACK" => {
+                let item = self
+                    .alt_stack
+                    .pop()
+                    .ok_or_else(|| "alt stack is empty".to_string())?;
+                self.stack.push(item);
+            }
+            "OP_2DROP" => {
+                self.require(2)?;
+                self.stack.truncate(self.stack.len() - 2);
+            }
+            "OP_2DUP" => self.copy_range(2, 2)?,
+            "OP_3DUP" => self.copy_range(3, 3)?,
+            "OP_2OVER" => self.copy_range(4, 2)?,
+            "OP_2ROT" => {
+                self.require(6)?;
+                let start = self.stack.len() - 6;
+                self.stack[start..].rotate_left(2);
+            }
+            "OP_2SWAP" => {
+                self.require(4)?;
+                let start = self.stack.len() - 4;
+                self.stack[start..].rotate_left(2);
+            }
+            "OP_IFDUP" => {
+                let top = self.peek(0)?.clone();
+                if cast_to_bool(&top) {
+                    self.stack.push(top);
+                }
+            }
+            "OP_DEPTH" => self.stack.push(encode_num(self.stack.len() as i64)),
+            "OP_DROP" => {
+                self.pop()?;
+            }
+            "OP_DUP" => self.copy_range(1, 1)?,
+            "OP_NIP" => {
+                self.require(2)?;
+                self.stack.remove(self.stack.len() - 2);
+            }
+            "OP_OVER" => self.copy_range(2, 1)?,
+            "OP_PICK" | "OP_ROLL" => {
+                let depth = decode_num(&self.pop()?, 4)?;
+                if depth < 0 || depth as usize >= self.stack.len() {
+                    return Err(format!("depth {} is outside the stack", depth));
+                }
+                let position = self.stack.len() - 1 - depth as usize;
+                let item = if name == "OP_ROLL" {
+                    self.stack.remove(position)
+                } else {
+                    self.stack[position].clone()
+                };
+                self.stack.push(item);
+            }
+            "OP_ROT" => {
+                self.require(3)?;
+                let start = self.stack.len() - 3;
+                self.stack[start..].rotate_left(1);
+            }
+            "OP_SWAP" => {
+                self.require(2)?;
+                let len = self.stack.len();
+                self.stack.swap(len - 1, len - 2);
+            }
+            "OP_TUCK" => {
+                let top = self.peek(0)?.clone();
+                self.require(2)?;
+                self.stack.insert(self.stack.len() - 2, top);
+            }
+            "OP_SIZE" => {
+                let size = self.peek(0)?.len();
+                self.stack.push(encode_num(size as i64));
+            }
+
+            "OP_EQUAL" | "OP_EQUALVERIFY" => {
+                let b = self.pop()?;
+                let a = self.pop()?;
+                self.push_result(name, a == b, "values are not equal")?;
+            }
+
+            "OP_1ADD" | "OP_1SUB" | "OP_NEGATE" | "OP_ABS" | "OP_NOT" | "OP_0NOTEQUAL" => {
+                let a = decode_num(&self.pop()?, 4)?;
+                let result = match name {
+                    "OP_1ADD" => a + 1,
+                    "OP_1SUB" => a - 1,
+                    "OP_NEGATE" => -a,
+                    "OP_ABS" => a.abs(),
+                    "OP_NOT" => (a == 0) as i64,
+                    _ => (a != 0) as i64,
+                };
+                self.stack.push(encode_num(result));
+            }
+            "OP_ADD" | "OP_SUB" | "OP_BOOLAND" | "OP_BOOLOR" | "OP_NUMEQUAL"
+            | "OP_NUMEQUALVERIFY" | "OP_NUMNOTEQUAL" | "OP_LESSTHAN" | "OP_GREATERTHAN"
+            | "OP_LESSTHANOREQUAL" | "OP_GREATERTHANOREQUAL" | "OP_MIN" | "OP_MAX" => {
+                let b = decode_num(&self.pop()?, 4)?;
+                let a = decode_num(&self.pop()?, 4)?;
+                let result = match name {
+                    "OP_ADD" => a + b,
+                    "OP_SUB" => a - b,
+                    "OP_BOOLAND" => (a != 0 && b != 0) as i64,
+                    "OP_BOOLOR" => (a != 0 || b != 0) as i64,
+                    "OP_NUMEQUAL" | "OP_NUMEQUALVERIFY" => (a == b) as i64,
+                    "OP_NUMNOTEQUAL" => (a != b) as i64,
+                    "OP_LESSTHAN" => (a < b) as i64,
+                    "OP_GREATERTHAN" => (a > b) as i64,
+                    "OP_LESSTHANOREQUAL" => (a <= b) as i64,
+                    "OP_GREATERTHANOREQUAL" => (a >= b) as i64,
+                    "OP_MIN" => a.min(b),
+                    _ => a.max(b),
+                };
+                if name == "OP_NUMEQUALVERIFY" {
+                    if result == 0 {
+                        return Err("numbers are not equal".to_string());
+                    }
+                } else {
+                    self.stack.push(encode_num(result));
+                }
+            }
+            "OP_WITHIN" => {
+                let max = decode_num(&self.pop()?, 4)?;
+                let min = decode_num(&self.pop()?, 4)?;
+                let x = decode_num(&self.pop()?, 4)?;
+                self.stack.push(encode_num((min <= x && x < max) as i64));
+            }
+
+            "OP_RIPEMD160" => self.hash(|data| ripemd160::Hash::hash(data).to_byte_array().to_vec())?,
+            "OP_SHA1" => self.hash(|data| sha1::Hash::hash(data).to_byte_array().to_vec())?,
+            "OP_SHA256" => self.hash(|data| sha256::Hash::hash(data).to_byte_array().to_vec())?,
+            "OP_HASH160" => self.hash(|data| hash160::Hash::hash(data).to_byte_array().to_vec())?,
+            "OP_HASH256" => self.hash(|data| sha256d::Hash::hash(data).to_byte_array().to_vec())?,
+            "OP_CODESEPARATOR" => self.code_separator = instruction.offset + 1,
+
+            "OP_CHECKSIG" | "OP_CHECKSIGVERIFY" => {
+                let public_key = self.pop()?;
+                let signature = self.pop()?;
+                let valid = !signature.is_empty()
+                    && self
+                        .context
+                        .check_signature(&signature, &public_key, self.script_code());
+                if !valid && !signature.is_empty() {
+                    return Err("failing signature must be empty (NULLFAIL)".to_string());
+                }
+                self.push_result(name, valid, "signature does not verify")?;
+            }
+            "OP_CHECKMULTISIG" | "OP_CHECKMULTISIGVERIFY" => self.check_multisig(name)?,
+
+            "OP_CLTV" => self.check_lock_time()?,
+            "OP_CSV" => self.check_sequence()?,
+
+            _ if instruction.opcode >= 0xc1 => {
+                return Err("opcode is only enabled in Elements tapscript".to_string());
+            }
+            _ => return Err(format!("opcode 0x{:02x} is invalid", instruction.opcode)),
+        }
+
+        Ok(())
+    }
+
+    fn check_multisig(&mut self, name: &str) -> StepResult {
+        let key_count = decode_num(&self.pop()?, 4)?;
+        if !(0..=20).contains(&key_count) {
+            return Err(format!("key count {} is outside 0..=20", key_count));
+        }
+        self.op_count += key_count as usize;
+        if self.op_count > MAX_OPS {
+            return Err(format!("script has more than {} opcodes", MAX_OPS));
+        }
+        let mut keys = Vec::with_capacity(key_count as usize);
+        for _ in 0..key_count {
+            keys.push(self.pop()?);
+        }
+
+        let sig_count = decode_num(&self.pop()?, 4)?;
+        if sig_count < 0 || sig_count > key_count {
+            return Err(format!("signature count {} is outside 0..={}", sig_count, key_count));
+        }
+        let mut signatures = Vec::with_capacity(sig_count as usize);
+        for _ in 0..sig_count {
+            signatures.push(self.pop()?);
+        }
+
+        // The extra item consumed by the off-by-one bug must be empty (NULLDUMMY).
+        if !self.pop()?.is_empty() {
+            return Err("dummy element must be empty (NULLDUMMY)".to_string());
+        }
+
+        // Both lists were popped top first, so they run from the last key and
+        // signature backwards, which is the order consensus matches them in.
+        let mut keys = keys.iter();
+        let mut valid = true;
+        for signature in &signatures {
+            let matched = loop {
+                let Some(key) = keys.next() else {
+                    break false;
+                };
+                if !signature.is_empty()
+                    && self.context.check_signature(signature, key, self.script_code())
+                {
+                    break true;
+                }
+            };
+            if !matched {
+                valid = false;
+                break;
+            }
+        }
+
+        if !valid && signatures.iter().any(|signature| !signature.is_empty()) {
+            return Err("failing signatures must all be empty (NULLFAIL)".to_string());
+        }
+
+        self.push_result(name, valid, "signatures do not match the keys in order")
+    }
+
+    fn check_lock_time(&self) -> StepResult {
+        let lock_time = decode_num(self.peek(0)?, 5)?;
+        if lock_time < 0 {
+            return Err("negative locktime".to_string());
+        }
+        // Up to 5 bytes are allowed, so the comparison stays in i64 rather
+        // than truncating values of 2^32 and above.
+        let tx_lock_time = i64::from(self.context.lock_time());
+        let threshold = i64::from(LOCKTIME_THRESHOLD);
+
+        if (lock_time < threshold) != (tx_lock_time < threshold) {
+            return Err("locktime type does not match the transaction's".to_string());
+        }
+        if lock_time > tx_lock_time {
+            return Err(format!(
+                "transaction locktime {} is below the required {}",
+                tx_lock_time, lock_time
+            ));
+        }
+        if self.context.sequence() == u32::MAX {
+            return Err("input sequence is final, which disables the locktime".to_string());
+        }
+
+        Ok(())
+    }
+
+    fn check_sequence(&self) -> StepResult {
+        let sequence = decode_num(self.peek(0)?, 5)?;
+        if sequence < 0 {
+            return Err("negative sequence".to_string());
+        }
+        let sequence = sequence as u32;
+        if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return Ok(());
+        }
+
+        if self.context.version() < 2 {
+            return Err("transaction version must be at least 2".to_string());
+        }
+        let tx_sequence = self.context.sequence();
+        if tx_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return Err("input sequence has relative locktime disabled".to_string());
+        }
+
+        let mask = SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
+        let (required, actual) = (sequence & mask, tx_sequence & mask);
+        if (required & SEQUENCE_LOCKTIME_TYPE_FLAG) != (actual & SEQUENCE_LOCKTIME_TYPE_FLAG) {
+            return Err("relative locktime type does not match the input sequence".to_string());
+        }
+        if required > actual {
+            return Err(format!(
+                "input sequence {} is below the required {}",
+                actual & SEQUENCE_LOCKTIME_MASK,
+                required & SEQUENCE_LOCKTIME_MASK
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn script_code(&self) -> &[u8] {
+        &self.script[self.code_separator..]
+    }
+
+    fn push_result(&mut self, name: &str, valid: bool, failure: &str) -> StepResult {
+        if name.ends_with("VERIFY") {
+            if !valid {
+                return Err(failure.to_string());
+            }
+        } else {
+            self.stack.push(if valid { vec![1] } else { Vec::new() });
+        }
+        Ok(())
+    }
+
+    fn hash(&mut self, digest: impl Fn(&[u8]) -> Vec<u8>) -> StepResult {
+        let data = self.pop()?;
+        self.stack.push(digest(&data));
+        Ok(())
+    }
+
+    /// Pushes copies of `count` items starting `depth` items from the top.
+    fn copy_range(&mut self, depth: usize, count: usize) -> StepResult {
+        self.require(depth)?;
+        let start = self.stack.len() - depth;
+        let copies = self.stack[start..start + count].to_vec();
+        self.stack.extend(copies);
+        Ok(())
+    }
+
+    fn require(&self, count: usize) -> StepResult {
+        if self.stack.len() < count {
+            return Err(format!(
+                "needs {} stack items but only {} are left",
+                count,
+                self.stack.len()
+            ));
+        }
+        Ok(())
+    }
+
+    fn peek(&self, depth: usize) -> std::result::Result<&Vec<u8>, String> {
+        self.require(depth + 1)?;
+        Ok(&self.stack[self.stack.len() - 1 - depth])
+    }
+
+    fn pop(&mut self) -> std::result::Result<Vec<u8>, String> {
+        self.stack.pop().ok_or_else(|| "stack is empty".to_string())
+    }
+
+    fn check_stack_size(&self) -> StepResult {
+        let size = self.stack.len() + self.alt_stack.len();
+        if size > MAX_STACK_SIZE {
+            return Err(format!("stack size {} exceeds {}", size, MAX_STACK_SIZE));
+        }
+        Ok(())
+    }
+}
+
+fn cast_to_bool(bytes: &[u8]) -> bool {
+    bytes
+        .iter()
+        .enumerate()
+        .any(|(i, byte)| *byte != 0 && !(i == bytes.len() - 1 && *byte == 0x80))
+}
+
+/// Decodes a minimally encoded script number of at most `max_len` bytes.
+pub(crate) fn decode_num(bytes: &[u8], max_len: usize) -> std::result::Result<i64, String> {
+    if bytes.len() > max_len {
+        return Err(format!("number is {} bytes, more than {}", bytes.len(), max_len));
+    }
+    let Some(&last) = bytes.last() else {
+        return Ok(0);
+    };
+    if last & 0x7f == 0 && (bytes.len() == 1 || bytes[bytes.len() - 2] & 0x80 == 0) {
+        return Err("number is not minimally encoded".to_string());
+    }
+
+    let mut value = 0i64;
+    for (i, byte) in bytes.iter().enumerate() {
+        value |= (*byte as i64) << (8 * i);
+    }
+    if last & 0x80 != 0 {
+        let sign_bit = 0x80i64 << (8 * (bytes.len() - 1));
+        return Ok(-(value & !sign_bit));
+    }
+
+    Ok(value)
+}
+
+fn encode_num(value: i64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut magnitude = value.unsigned_abs();
+    while magnitude > 0 {
+        bytes.push((magnitude & 0xff) as u8);
+        magnitude >>= 8;
+    }
+
+    if let Some(last) = bytes.last_mut() {
+        if *last & 0x80 != 0 {
+            bytes.push(if value < 0 { 0x80 } else { 0x00 });
+        } else if value < 0 {
+            *last |= 0x80;
+        }
+    }
+
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accepts a signature exactly when it equals the public key, so tests
+    /// can pick which signatures verify without real keys.
+    struct TestContext {
+        lock_time: u32,
+        sequence: u32,
+        version: u32,
+    }
+
+    impl SpendContext for TestContext {
+        fn check_signature(&self, signature: &[u8], public_key: &[u8], _: &[u8]) -> bool {
+            signature == public_key
+        }
+
+        fn lock_time(&self) -> u32 {
+            self.lock_time
+        }
+
+        fn sequence(&self) -> u32 {
+            self.sequence
+        }
+
+        fn version(&self) -> u32 {
+            self.version
+        }
+    }
+
+    const CONTEXT: TestContext = TestContext {
+        lock_time: 800_000,
+        sequence: 10,
+        version: 2,
+    };
+
+    fn run(script: &[u8], witness: &[&[u8]]) -> Trace {
+        run_with(&CONTEXT, script, witness)
+    }
+
+    fn run_with(context: &TestContext, script: &[u8], witness: &[&[u8]]) -> Trace {
+        let witness = witness.iter().map(|item| item.to_vec()).collect();
+        interpret(script, witness, context).unwrap()
+    }
+
+    fn assert_fails(trace: &Trace, reason: &str) {
+        assert!(!trace.success, "script unexpectedly succeeded");
+        let error = trace.error.as_deref().unwrap();
+        assert!(
+            error.contains(reason),
+            "`{}` does not mention `{}`",
+            error,
+            reason
+        );
+    }
+
+    #[test]
+    fn pushes_numbers_and_data() {
+        // OP_1NEGATE OP_16 OP_0 0x02 abcd OP_2DROP OP_2DROP OP_1
+        let trace = run(&[0x4f, 0x60, 0x00, 0x02, 0xab, 0xcd, 0x6d, 0x6d, 0x51], &[]);
+        assert!(trace.success);
+        assert_eq!(trace.steps[0].stack, ["81"]);
+        assert_eq!(trace.steps[1].stack, ["81", "10"]);
+        assert_eq!(trace.steps[2].stack, ["81", "10", ""]);
+        assert_eq!(trace.steps[3].data.as_deref(), Some("abcd"));
+        assert_eq!(trace.steps[3].stack, ["81", "10", "", "abcd"]);
+    }
+
+    #[test]
+    fn runs_stack_opcodes() {
+        let cases: &[(u8, &[&[u8]], &[&str])] = &[
+            (0x76, &[b"\x01"], &["01", "01"]),
+            (0x75, &[b"\x01", b"\x02"], &["01"]),
+            (0x78, &[b"\x01", b"\x02"], &["01", "02", "01"]),
+            (0x7c, &[b"\x01", b"\x02"], &["02", "01"]),
+            (0x7b, &[b"\x01", b"\x02", b"\x03"], &["02", "03", "01"]),
+            (0x77, &[b"\x01", b"\x02"], &["02"]),
+            (0x7d, &[b"\x01", b"\x02"], &["02", "01", "02"]),
+            (0x74, &[b"\x01", b"\x02"], &["01", "02", "02"]),
+            (0x6d, &[b"\x01", b"\x02", b"\x03"], &["01"]),
+            (0x6e, &[b"\x01", b"\x02"], &["01", "02", "01", "02"]),
+            (
+                0x6f,
+                &[b"\x01", b"\x02", b"\x03"],
+                &["01", "02", "03", "01", "02", "03"],
+            ),
+            (
+                0x70,
+                &[b"\x01", b"\x02", b"\x03", b"\x04"],
+                &["01", "02", "03", "04", "01", "02"],
+            ),
+            (
+                0x72,
+                &[b"\x01", b"\x02", b"\x03", b"\x04"],
+                &["03", "04", "01", "02"],
+            ),
+            (
+                0x71,
+                &[b"\x01", b"\x02", b"\x03", b"\x04", b"\x05", b"\x06"],
+                &["03", "04", "05", "06", "01", "02"],
+            ),
+            // OP_IFDUP only duplicates true values; OP_SIZE leaves the item in place.
+            (0x73, &[b""], &[""]),
+            (0x73, &[b"\x05"], &["05", "05"]),
+            (0x82, &[b"\x05\x06"], &["0506", "02"]),
+        ];
+        for (opcode, witness, expected) in cases {
+            assert_eq!(
+                run(&[*opcode], witness).final_stack,
+                *expected,
+                "opcode 0x{:02x}",
+                opcode
+            );
+        }
+
+        // 2 OP_PICK copies the third item from the top, 2 OP_ROLL moves it.
+        let witness: &[&[u8]] = &[b"\x07", b"\x08", b"\x09"];
+        assert_eq!(
+            run(&[0x52, 0x79], witness).final_stack,
+            ["07", "08", "09", "07"]
+        );
+        assert_eq!(run(&[0x52, 0x7a], witness).final_stack, ["08", "09", "07"]);
+
+        // OP_TOALTSTACK OP_FROMALTSTACK
+        let trace = run(&[0x6b, 0x6c], &[b"\x01"]);
+        assert_eq!(trace.steps[0].stack, Vec::<String>::new());
+        assert_eq!(trace.steps[0].alt_stack, ["01"]);
+        assert!(trace.success);
+    }
+
+    #[test]
+    fn rejects_stack_underflow() {
+        assert_fails(&run(&[0x75], &[]), "stack is empty");
+        assert_fails(&run(&[0x7c], &[b"\x01"]), "needs 2 stack items");
+        assert_fails(&run(&[0x6c], &[b"\x01"]), "alt stack is empty");
+        assert_fails(&run(&[0x52, 0x79], &[b"\x01"]), "outside the stack");
+    }
+
+    #[test]
+    fn runs_arithmetic() {
+        // 5 3 OP_SUB 2 OP_NUMEQUAL
+        assert!(run(&[0x55, 0x53, 0x94, 0x52, 0x9c], &[]).success);
+        // 2 OP_NEGATE OP_ABS OP_1ADD 3 OP_NUMEQUALVERIFY OP_1
+        assert!(run(&[0x52, 0x8f, 0x90, 0x8b, 0x53, 0x9d, 0x51], &[]).success);
+        assert_eq!(run(&[0x51, 0x52, 0x93], &[]).final_stack, ["03"]);
+        assert_eq!(run(&[0x51, 0x8c, 0x8c], &[]).final_stack, ["81"]);
+        assert_eq!(run(&[0x52, 0x53, 0xa3], &[]).final_stack, ["02"]);
+        assert_eq!(run(&[0x52, 0x53, 0xa4], &[]).final_stack, ["03"]);
+        // 3 within [2, 4)
+        assert!(run(&[0x53, 0x52, 0x54, 0xa5], &[]).success);
+        assert!(!run(&[0x54, 0x52, 0x54, 0xa5], &[]).success);
+        assert!(run(&[0x00, 0x91], &[]).success);
+        assert!(run(&[0x51, 0x00, 0x9b], &[]).success);
+        assert!(!run(&[0x51, 0x00, 0x9a], &[]).success);
+        assert!(run(&[0x51, 0x52, 0x9f], &[]).success);
+    }
+
+    #[test]
+    fn rejects_non_minimal_and_oversized_numbers() {
+        assert_fails(&run(&[0x8b], &[b"\x01\x00"]), "not minimally encoded");
+        assert_fails(&run(&[0x8b], &[b"\x01\x02\x03\x04\x05"]), "more than 4");
+        assert_eq!(decode_num(&[0xff, 0x00], 4), Ok(255));
+        assert_eq!(decode_num(&[0x81], 4), Ok(-1));
+        assert_eq!(encode_num(-255), [0xff, 0x80]);
+        assert_eq!(encode_num(0), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn runs_hash_and_equality_opcodes() {
+        let hash = sha256::Hash::hash(b"abc").to_byte_array();
+        let mut script = vec![0xa8, 0x20];
+        script.extend_from_slice(&hash);
+        script.push(0x87);
+        assert!(run(&script, &[b"abc"]).success);
+        assert!(!run(&script, &[b"abd"]).success);
+
+        assert_eq!(
+            run(&[0xa9], &[b""]).final_stack,
+            ["b472a266d0bd89c13706a4132ccfb16f7c3b9fcb"]
+        );
+        assert_eq!(
+            run(&[0xa6], &[b""]).final_stack,
+            ["9c1185a5c5e9fc54612808977ee8f548b2258d31"]
+        );
+        assert_eq!(
+            run(&[0xa7], &[b""]).final_stack,
+            ["da39a3ee5e6b4b0d3255bfef95601890afd80709"]
+        );
+        assert_eq!(
+            run(&[0xaa], &[b""]).final_stack,
+            ["5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456"]
+        );
+        assert_fails(
+            &run(&[0x88, 0x51], &[b"\x01", b"\x02"]),
+            "values are not equal",
+        );
+    }
+
+    #[test]
+    fn follows_branches() {
+        // OP_IF 2 OP_ELSE 3 OP_ENDIF
+        let script = [0x63, 0x52, 0x67, 0x53, 0x68];
+        assert_eq!(run(&script, &[b"\x01"]).final_stack, ["02"]);
+        let trace = run(&script, &[b""]);
+        assert_eq!(trace.final_stack, ["03"]);
+        assert!(!trace.steps[1].executed);
+        assert!(trace.steps[3].executed);
+        // OP_NOTIF inverts the condition.
+        assert_eq!(
+            run(&[0x64, 0x52, 0x67, 0x53, 0x68], &[b""]).final_stack,
+            ["02"]
+        );
+
+        assert_fails(&run(&[0x63, 0x51], &[b"\x01"]), "missing OP_ENDIF");
+        assert_fails(&run(&[0x68], &[b"\x01"]), "without a matching OP_IF");
+        assert_fails(&run(&[0x67], &[b"\x01"]), "without a matching OP_IF");
+    }
+
+    #[test]
+    fn enforces_minimal_if() {
+        let script = [0x63, 0x51, 0x68];
+        assert_fails(&run(&script, &[b"\x02"]), "MINIMALIF");
+        assert_fails(&run(&script, &[b"\x01\x00"]), "MINIMALIF");
+        assert_fails(&run(&script, &[b"\x00"]), "MINIMALIF");
+    }
+
+    #[test]
+    fn rejects_disabled_and_invalid_opcodes() {
+        assert_fails(&run(&[0x7e], &[b"\x01", b"\x02"]), "disabled");
+        assert_fails(&run(&[0x95], &[b"\x01", b"\x02"]), "disabled");
+        assert_fails(&run(&[0x6a], &[b"\x01"]), "exits early");
+        assert_fails(&run(&[0x69], &[b"\x01", b""]), "top stack item is false");
+        // Disabled opcodes fail even in a branch that is not taken.
+        assert_fails(&run(&[0x00, 0x63, 0x7e, 0x68, 0x51], &[]), "disabled");
+        // OP_2MUL and OP_2DIV
+        assert_fails(&run(&[0x00, 0x63, 0x8d, 0x68, 0x51], &[]), "disabled");
+        assert_fails(&run(&[0x00, 0x63, 0x8e, 0x68, 0x51], &[]), "disabled");
+        assert_fails(&run(&[0xc1], &[b"\x01"]), "Elements tapscript");
+    }
+
+    #[test]
+    fn checks_signatures() {
+        // <key> OP_CHECKSIG, where only a signature equal to the key verifies.
+        let script = [0x01, 0x07, 0xac];
+        assert!(run(&script, &[b"\x07"]).success);
+        assert_fails(&run(&script, &[b""]), "false value");
+        assert_fails(
+            &run(&[0x01, 0x07, 0xad, 0x51], &[b""]),
+            "signature does not verify",
+        );
+    }
+
+    #[test]
+    fn enforces_null_fail() {
+        assert_fails(&run(&[0x01, 0x07, 0xac], &[b"\x08"]), "NULLFAIL");
+        // 1 <a> <b> 2 OP_CHECKMULTISIG
+        let script = [0x51, 0x01, 0x0a, 0x01, 0x0b, 0x52, 0xae];
+        assert_fails(&run(&script, &[b"", b"\x0c"]), "NULLFAIL");
+        assert_fails(&run(&script, &[b"", b""]), "false value");
+    }
+
+    #[test]
+    fn checks_multisig_in_key_order() {
+        // 2 <a> <b> <c> 3 OP_CHECKMULTISIG
+        let script = [0x52, 0x01, 0x0a, 0x01, 0x0b, 0x01, 0x0c, 0x53, 0xae];
+        assert!(run(&script, &[b"", b"\x0a", b"\x0c"]).success);
+        assert!(run(&script, &[b"", b"\x0b", b"\x0c"]).success);
+        // Signatures in the wrong order can no longer match.
+        assert_fails(&run(&script, &[b"", b"\x0c", b"\x0a"]), "NULLFAIL");
+    }
+
+    #[test]
+    fn enforces_null_dummy() {
+        let script = [0x51, 0x01, 0x0a, 0x51, 0xae];
+        assert!(run(&script, &[b"", b"\x0a"]).success);
+        assert_fails(&run(&script, &[b"\x00", b"\x0a"]), "NULLDUMMY");
+        assert_fails(&run(&script, &[b"\x0a"]), "stack is empty");
+    }
+
+    #[test]
+    fn checks_absolute_lock_time() {
+        // <locktime> OP_CLTV
+        let script = |lock_time: &[u8]| {
+            let mut script = vec![lock_time.len() as u8];
+            script.extend_from_slice(lock_time);
+            script.push(0xb1);
+            script
+        };
+        let at = |height: u32| encode_num(height as i64);
+
+        assert!(run(&script(&at(800_000)), &[]).success);
+        assert!(run(&script(&at(799_999)), &[]).success);
+        assert_fails(&run(&script(&at(800_001)), &[]), "below the required");
+        assert_fails(
+            &run(&script(&at(LOCKTIME_THRESHOLD)), &[]),
+            "type does not match",
+        );
+        assert_fails(&run(&script(&[0x81]), &[]), "negative locktime");
+
+        // A 5-byte locktime must not wrap around: truncated to 32 bits,
+        // 2^32 + 500_000_001 would pass as 500_000_001.
+        let time_locked = TestContext {
+            lock_time: 600_000_000,
+            ..CONTEXT
+        };
+        assert!(run_with(&time_locked, &script(&encode_num(500_000_001)), &[]).success);
+        assert_fails(
+            &run_with(
+                &time_locked,
+                &script(&encode_num((1 << 32) + 500_000_001)),
+                &[],
+            ),
+            "below the required",
+        );
+
+        let final_input = TestContext {
+            sequence: u32::MAX,
+            ..CONTEXT
+        };
+        assert_fails(
+            &run_with(&final_input, &script(&at(1)), &[]),
+            "sequence is final",
+        );
+    }
+
+    #[test]
+    fn checks_relative_lock_time() {
+        // <sequence> OP_CSV
+        let script = |sequence: u32| {
+            let sequence = encode_num(sequence as i64);
+            let mut script = vec![sequence.len() as u8];
+            script.extend_from_slice(&sequence);
+            script.push(0xb2);
+            script
+        };
+
+        assert!(run(&script(10), &[]).success);
+        assert_fails(&run(&script(11), &[]), "below the required");
+        assert_fails(
+            &run(&script(SEQUENCE_LOCKTIME_TYPE_FLAG | 1), &[]),
+            "type does not match",
+        );
+        // The disable flag turns the check into a no-op.
+        assert!(run(&script(SEQUENCE_LOCKTIME_DISABLE_FLAG | 100), &[]).success);
+
+        let version_one = TestContext {
+            version: 1,
+            ..CONTEXT
+        };
+        assert_fails(
+            &run_with(&version_one, &script(1), &[]),
+            "version must be at least 2",
+        );
+        let disabled = TestContext {
+            sequence: SEQUENCE_LOCKTIME_DISABLE_FLAG,
+            ..CONTEXT
+        };
+        assert_fails(
+            &run_with(&disabled, &script(1), &[]),
+            "relative locktime disabled",
+        );
+    }
+
+    #[test]
+    fn enforces_op_count_limit() {
+        let mut script = vec![0x61; MAX_OPS];
+        script.push(0x51);
+        assert!(run(&script, &[]).success);
+
+        script.insert(0, 0x61);
+        assert_fails(&run(&script, &[]), "more than 201 opcodes");
+        // Keys of an OP_CHECKMULTISIG count towards the limit too.
+        let mut script = vec![0x61; MAX_OPS - 20];
+        script.extend_from_slice(&[0x01, 0x14, 0xae]);
+        assert_fails(&run(&script, &[]), "more than 201 opcodes");
+    }
+
+    #[test]
+    fn enforces_stack_and_element_limits() {
+        // Pushes do not count as opcodes, so only the stack limit applies.
+        let script = vec![0x51; MAX_STACK_SIZE];
+        assert_fails(&run(&script, &[b"\x01"]), "stack size 1001 exceeds 1000");
+
+        let big = [0u8; MAX_ELEMENT_SIZE + 1];
+        assert_fails(&run(&[0x51], &[&big]), "larger than 520 bytes");
+
+        let mut script = vec![0x4d, 0x09, 0x02];
+        script.extend_from_slice(&big);
+        assert_fails(&run(&script, &[]), "push of 521 bytes");
+
+        let script = vec![0x61; MAX_SCRIPT_SIZE + 1];
+        assert_fails(&run(&script, &[]), "more than 10000");
+    }
+
+    #[test]
+    fn rejects_truncated_scripts() {
+        assert!(interpret(&[0x02, 0x01], Vec::new(), &CONTEXT).is_err());
+    }
+
+    #[test]
+    fn requires_a_single_true_item() {
+        assert_fails(&run(&[], &[b"\x01", b"\x01"]), "exactly one item");
+        assert_fails(&run(&[], &[b"\x80"]), "false value");
+        assert!(run(&[], &[b"\x00\x01"]).success);
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::{Context, Result};
+use wasm_bindgen::prelude::*;
+use serde::Deserialize;
+use elements::bitcoin::{
+    Amount, EcdsaSighashType, Script, ScriptBuf, Transaction, hashes::Hash, psbt::Psbt,
+    sighash::SighashCache,
+};
+
+use crate::interpreter::{SpendContext, decode_witness, interpret, verify_ecdsa};
+
+#[derive(Deserialize)]
+pub struct InterpretPsbtRequest {
+    pub psbt_hex: String,
+    pub input_index: usize,
+    pub witness_script_hex: String,
+    /// Hex-encoded witness stack items, bottom first, without the witness script.
+    pub witness: Vec<String>,
+}
+
+#[wasm_bindgen]
+pub fn interpret_psbt(request_json: JsValue) -> Result<JsValue, JsValue> {
+    let request: InterpretPsbtRequest = serde_wasm_bindgen::from_value(request_json)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse request: {}", e)))?;
+
+    let result = execute(
+        &request.psbt_hex,
+        request.input_index,
+        &request.witness_script_hex,
+        &request.witness,
+    );
+
+    match result {
+        Ok(output) => serde_wasm_bindgen::to_value(&output)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize response: {}", e))),
+        Err(e) => Err(JsValue::from_str(&e.to_string())),
+    }
+}
+
+/// Runs a P2WSH witness script against input `input_index` of a PSBT and
+/// returns the stack after every opcode.
+pub fn execute(
+    psbt_hex: &str,
+    input_index: usize,
+    witness_script_hex: &str,
+    witness_hex: &[String],
+) -> Result<serde_json::Value> {
+    let psbt_bytes = hex::decode(psbt_hex).context("Failed to decode PSBT hex")?;
+    let psbt: Psbt = Psbt::deserialize(&psbt_bytes).context("Failed to deserialize PSBT")?;
+
+    if input_index >= psbt.inputs.len() {
+        return Err(anyhow::anyhow!(
+            "Input index {} out of bounds (PSBT has {} inputs)",
+            input_index,
+            psbt.inputs.len()
+        ));
+    }
+
+    let witness_script_bytes =
+        hex::decode(witness_script_hex).context("Failed to decode witness script hex")?;
+    let witness_script = ScriptBuf::from_bytes(witness_script_bytes);
+    let witness = decode_witness(witness_hex)?;
+
+    let utxo = psbt.inputs[input_index]
+        .witness_utxo
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Missing witness UTXO for input {}", input_index))?;
+
+    if utxo.script_pubkey.is_p2wsh() && utxo.script_pubkey != witness_script.to_p2wsh() {
+        return Err(anyhow::anyhow!(
+            "Witness script does not match the P2WSH output spent by input {}",
+            input_index
+        ));
+    }
+
+    let tx = psbt.clone().extract_tx_unchecked_fee_rate();
+    let context = PsbtContext {
+        tx: &tx,
+        input_index,
+        value: utxo.value,
+    };
+
+    let trace = interpret(witness_script.as_bytes(), witness, &context)?;
+
+    serde_json::to_value(trace).context("Failed to serialize trace")
+}
+
+struct PsbtContext<'a> {
+    tx: &'a Transaction,
+    input_index: usize,
+    value: Amount,
+}
+
+impl SpendContext for PsbtContext<'_> {
+    fn check_signature(&self, signature: &[u8], public_key: &[u8], script_code: &[u8]) -> bool {
+        let Some((&hash_type, der)) = signature.split_last() else {
+            return false;
+        };
+        let Ok(hash_type) = EcdsaSighashType::from_standard(hash_type as u32) else {
+            return false;
+        };
+
+        let Ok(sighash) = SighashCache::new(self.tx).p2wsh_signature_hash(
+            self.input_index,
+            Script::from_bytes(script_code),
+            self.value,
+            hash_type,
+        ) else {
+            return false;
+        };
+
+        verify_ecdsa(sighash.to_byte_array(), der, public_key)
+    }
+
+    fn lock_time(&self) -> u32 {
+        self.tx.lock_time.to_consensus_u32()
+    }
+
+    fn sequence(&self) -> u32 {
+        self.tx.input[self.input_index].sequence.to_consensus_u32()
+    }
+
+    fn version(&self) -> u32 {
+        self.tx.version.0 as u32
+    }
+}
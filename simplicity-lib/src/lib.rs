@@ -1,18 +1,31 @@
+pub mod analysis;
+pub mod coin_selection;
 pub mod compiler;
 pub mod converter;
 pub mod create_psbt;
 pub mod create_pset;
+pub mod dry_run;
+pub mod dry_run_psbt;
+pub mod fee;
 pub mod finalize;
 pub mod finalize_psbt;
+pub mod interpreter;
+pub mod interpreter_psbt;
+pub mod policy;
 pub mod sighash;
 pub mod sighash_psbt;
 
 // Re-export main functions for easier access
-pub use compiler::compile;
-pub use converter::convert_script;
+pub use analysis::analyze_script;
+pub use compiler::{compile, tweak_key, witness_abi};
+pub use converter::{build_multisig_template, convert_script, disassemble_script};
 pub use create_psbt::create_psbt;
 pub use create_pset::create_pset;
+pub use dry_run::dry_run_pset;
+pub use dry_run_psbt::dry_run_psbt;
 pub use finalize::finalize_pset;
 pub use finalize_psbt::finalize_psbt;
+pub use interpreter::interpret_pset;
+pub use interpreter_psbt::interpret_psbt;
 pub use sighash::sighash_pset;
 pub use sighash_psbt::sighash_psbt;
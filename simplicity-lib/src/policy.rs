@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use anyhow::{Result, anyhow};
+use elements::bitcoin::PublicKey;
+use elements::bitcoin::hashes::{hash160, ripemd160, sha256};
+use miniscript::descriptor::{Descriptor, ShInner};
+use miniscript::policy::{Concrete, Liftable, Semantic};
+use miniscript::{Miniscript, Segwitv0, TranslateErr, Translator, hash256};
+
+use crate::converter::AddressType;
+
+/// Most spending paths listed before giving up, since thresholds multiply them.
+const MAX_SPENDING_PATHS: usize = 64;
+
+/// A witness or redeem script compiled from miniscript.
+///
+/// Miniscript only emits opcodes that behave the same on Bitcoin and Elements
+/// in segwit v0 and legacy scripts, so the same bytes serve both chains.
+/// Elements-only fragments (introspection covenants) are not supported.
+#[derive(Debug)]
+pub struct CompiledPolicy {
+    pub script: Vec<u8>,
+    pub address_type: AddressType,
+    /// Descriptor with hex keys and checksum.
+    pub descriptor: String,
+    /// Spending conditions lifted from the miniscript, with keys as written.
+    pub spending_policy: String,
+    /// Each way to satisfy the script, as the signatures, timelocks and hash
+    /// preimages it needs. `None` if there are more than [`MAX_SPENDING_PATHS`].
+    pub spending_paths: Option<Vec<Vec<String>>>,
+    /// Worst-case weight of the `scriptSig` and witness needed to spend, over
+    /// all paths. Miniscript only plans a single path given the keys, hashes
+    /// and timelocks available, so there is no weight per path.
+    pub max_satisfaction_weight: Option<u64>,
+}
+
+/// Compiles a descriptor, miniscript fragment or concrete policy.
+///
+/// `wsh(...)`, `sh(wsh(...))` and `sh(...)` descriptors pick their own address
+/// type. Fragments such as `or_d(multi(2,A,B),and_v(v:pk(C),older(144)))` and
+/// policies such as `or(pk(A),and(pk(B),older(144)))` are wrapped according to
+/// `address_type`, which must then be `p2wsh` or `p2sh-p2wsh`.
+///
+/// Keys may be hex public keys or names looked up in `keys`.
+pub fn compile_policy(
+    input: &str,
+    keys: &HashMap<String, String>,
+    address_type: AddressType,
+) -> Result<CompiledPolicy> {
+    let input = input.trim();
+
+    let descriptor = if input.starts_with("wsh(") || input.starts_with("sh(") {
+        Descriptor::<String>::from_str(input).map_err(|e| anyhow!("Invalid descriptor: {}", e))?
+    } else if ["tr(", "wpkh(", "pkh(", "bare("]
+        .iter()
+        .any(|prefix| input.starts_with(prefix))
+    {
+        return Err(anyhow!(
+            "Only wsh(), sh(wsh()) and sh() descriptors are supported"
+        ));
+    } else {
+        let miniscript = parse_segwit_miniscript(input)?;
+        let wrapped = match address_type {
+            AddressType::P2wsh => Descriptor::new_wsh(miniscript),
+            AddressType::P2shP2wsh => Descriptor::new_sh_wsh(miniscript),
+            AddressType::P2sh | AddressType::P2tr => {
+                return Err(anyhow!(
+                    "Miniscript fragments and policies compile to p2wsh or p2sh-p2wsh; \
+                     use an sh(...) descriptor for legacy P2SH"
+                ));
+            }
+        };
+        wrapped.map_err(|e| anyhow!("Invalid miniscript: {}", e))?
+    };
+
+    let address_type = match &descriptor {
+        Descriptor::Wsh(_) => AddressType::P2wsh,
+        Descriptor::Sh(sh) => match sh.as_inner() {
+            ShInner::Wsh(_) => AddressType::P2shP2wsh,
+            ShInner::Wpkh(_) => return Err(anyhow!("sh(wpkh()) has no script to convert")),
+            _ => AddressType::P2sh,
+        },
+        _ => return Err(anyhow!("Only wsh(), sh(wsh()) and sh() descriptors are supported")),
+    };
+
+    let lifted = descriptor
+        .lift()
+        .map_err(|e| anyhow!("Failed to lift spending policy: {}", e))?;
+    let spending_policy = lifted.to_string();
+    let spending_paths = spending_paths(&lifted).map(|mut paths| {
+        paths.sort();
+        paths.dedup();
+        paths
+    });
+
+    let descriptor = descriptor
+        .translate_pk(&mut KeyMap(keys))
+        .map_err(|e| match e {
+            TranslateErr::TranslatorErr(e) => e,
+            TranslateErr::OuterError(e) => anyhow!("Invalid miniscript: {}", e),
+        })?;
+
+    let script = descriptor
+        .explicit_script()
+        .map_err(|e| anyhow!("Failed to encode script: {}", e))?;
+
+    Ok(CompiledPolicy {
+        script: script.into_bytes(),
+        address_type,
+        descriptor: descriptor.to_string(),
+        spending_policy,
+        spending_paths,
+        max_satisfaction_weight: descriptor.max_weight_to_satisfy().ok().map(|w| w.to_wu()),
+    })
+}
+
+/// Lists the sets of conditions that each satisfy `policy`, or `None` if there
+/// are more than [`MAX_SPENDING_PATHS`] of them.
+fn spending_paths(policy: &Semantic<String>) -> Option<Vec<Vec<String>>> {
+    let thresh = match policy {
+        Semantic::Unsatisfiable => return Some(Vec::new()),
+        Semantic::Trivial => return Some(vec![Vec::new()]),
+        Semantic::Thresh(thresh) => thresh,
+        leaf => return Some(vec![vec![leaf.to_string()]]),
+    };
+
+    let subs = thresh
+        .iter()
+        .map(|sub| spending_paths(sub))
+        .collect::<Option<Vec<_>>>()?;
+
+    let mut paths = Vec::new();
+    for chosen in combinations(subs.len(), thresh.k())? {
+        // Every chosen sub-policy must be satisfied, by any one of its paths.
+        let mut partial = vec![Vec::new()];
+        for i in chosen {
+            partial = partial
+                .iter()
+                .flat_map(|path: &Vec<String>| {
+                    subs[i].iter().map(move |sub_path| {
+                        let mut merged = path.clone();
+                        merged.extend(sub_path.iter().cloned());
+                        merged.sort();
+                        merged.dedup();
+                        merged
+                    })
+                })
+                .collect();
+            if partial.len() > MAX_SPENDING_PATHS {
+                return None;
+            }
+        }
+
+        paths.extend(partial);
+        if paths.len() > MAX_SPENDING_PATHS {
+            return None;
+        }
+    }
+
+    Some(paths)
+}
+
+/// All `k`-element subsets of `0..n`, or `None` if there are more than
+/// [`MAX_SPENDING_PATHS`].
+fn combinations(n: usize, k: usize) -> Option<Vec<Vec<usize>>> {
+    let mut count = 1usize;
+    for i in 0..k.min(n) {
+        count = count.checked_mul(n - i)? / (i + 1);
+        if count > MAX_SPENDING_PATHS {
+            return None;
+        }
+    }
+    if k > n {
+        return Some(Vec::new());
+    }
+
+    let mut result = Vec::new();
+    let mut chosen: Vec<usize> = (0..k).collect();
+    loop {
+        result.push(chosen.clone());
+
+        // Advance the rightmost index that still has room, then reset the ones after it.
+        let Some(i) = (0..k).rev().find(|&i| chosen[i] < n - k + i) else {
+            return Some(result);
+        };
+        chosen[i] += 1;
+        let mut next = chosen[i];
+        for slot in &mut chosen[i + 1..] {
+            next += 1;
+            *slot = next;
+        }
+    }
+}
+
+/// Parses a segwit v0 miniscript fragment, falling back to compiling it as a
+/// concrete policy.
+fn parse_segwit_miniscript(input: &str) -> Result<Miniscript<String, Segwitv0>> {
+    let fragment_error = match Miniscript::<String, Segwitv0>::from_str(input) {
+        Ok(miniscript) => return Ok(miniscript),
+        Err(e) => e,
+    };
+
+    let policy = Concrete::<String>::from_str(input).map_err(|policy_error| {
+        anyhow!(
+            "Not a miniscript fragment ({}) or policy ({})",
+            fragment_error,
+            policy_error
+        )
+    })?;
+
+    policy
+        .compile::<Segwitv0>()
+        .map_err(|e| anyhow!("Failed to compile policy: {}", e))
+}
+
+/// Resolves key names to public keys, taking anything not in the map as hex.
+struct KeyMap<'a>(&'a HashMap<String, String>);
+
+impl Translator<String, PublicKey, anyhow::Error> for KeyMap<'_> {
+    fn pk(&mut self, name: &String) -> Result<PublicKey> {
+        let key = self.0.get(name).unwrap_or(name);
+        PublicKey::from_str(key).map_err(|e| anyhow!("Invalid key `{}`: {}", name, e))
+    }
+
+    fn sha256(&mut self, hash: &String) -> Result<sha256::Hash> {
+        sha256::Hash::from_str(hash).map_err(|e| anyhow!("Invalid sha256 `{}`: {}", hash, e))
+    }
+
+    fn hash256(&mut self, hash: &String) -> Result<hash256::Hash> {
+        hash256::Hash::from_str(hash).map_err(|e| anyhow!("Invalid hash256 `{}`: {}", hash, e))
+    }
+
+    fn ripemd160(&mut self, hash: &String) -> Result<ripemd160::Hash> {
+        ripemd160::Hash::from_str(hash)
+            .map_err(|e| anyhow!("Invalid ripemd160 `{}`: {}", hash, e))
+    }
+
+    fn hash160(&mut self, hash: &String) -> Result<hash160::Hash> {
+        hash160::Hash::from_str(hash).map_err(|e| anyhow!("Invalid hash160 `{}`: {}", hash, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> HashMap<String, String> {
+        HashMap::from([
+            (
+                "A".to_string(),
+                "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".to_string(),
+            ),
+            (
+                "B".to_string(),
+                "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5".to_string(),
+            ),
+            (
+                "C".to_string(),
+                "02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9".to_string(),
+            ),
+        ])
+    }
+
+    fn paths(input: &str) -> Option<Vec<Vec<String>>> {
+        compile_policy(input, &keys(), AddressType::P2wsh)
+            .unwrap()
+            .spending_paths
+    }
+
+    #[test]
+    fn lists_each_branch() {
+        assert_eq!(
+            paths("or(pk(A),and(pk(B),older(144)))").unwrap(),
+            [vec!["older(144)", "pk(B)"], vec!["pk(A)"]]
+        );
+    }
+
+    #[test]
+    fn compiles_fragment_to_known_script() {
+        let compiled = compile_policy(
+            "or_d(pk(A),and_v(v:pk(B),older(144)))",
+            &keys(),
+            AddressType::P2wsh,
+        )
+        .unwrap();
+
+        // <A> OP_CHECKSIG OP_IFDUP OP_NOTIF <B> OP_CHECKSIGVERIFY 144 OP_CSV OP_ENDIF
+        assert_eq!(
+            hex::encode(&compiled.script),
+            "210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798ac7364\
+             2102c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5ad029000b268"
+        );
+        assert_eq!(compiled.address_type, AddressType::P2wsh);
+        assert_eq!(
+            compiled.descriptor,
+            "wsh(or_d(pk(0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798),\
+             and_v(v:pk(02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5),\
+             older(144))))#pktpjqtx"
+        );
+
+        let script = elements::bitcoin::ScriptBuf::from_bytes(compiled.script);
+        assert_eq!(
+            elements::bitcoin::Address::p2wsh(&script, elements::bitcoin::Network::Testnet)
+                .to_string(),
+            "tb1qsrv07d4p7dvkanfh4qn5c2dss92thendt8zftqagg3ja539znnts8m94ny"
+        );
+        assert_eq!(
+            compiled.spending_paths.unwrap(),
+            paths("or(pk(A),and(pk(B),older(144)))").unwrap()
+        );
+    }
+
+    #[test]
+    fn lists_each_threshold_combination() {
+        assert_eq!(
+            paths("thresh(2,pk(A),pk(B),pk(C))").unwrap(),
+            [
+                vec!["pk(A)", "pk(B)"],
+                vec!["pk(A)", "pk(C)"],
+                vec!["pk(B)", "pk(C)"]
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_combinations() {
+        assert_eq!(combinations(4, 2).unwrap().len(), 6);
+        assert_eq!(combinations(3, 0).unwrap(), [Vec::<usize>::new()]);
+        assert_eq!(combinations(2, 3).unwrap(), Vec::<Vec<usize>>::new());
+        assert!(combinations(20, 10).is_none());
+    }
+}